@@ -1,7 +1,8 @@
 use godot::builtin::Variant;
 use godot::prelude::*;
 use std::cmp::Ordering;
-use std::collections::BinaryHeap;
+use std::collections::{BTreeSet, BinaryHeap, HashMap, HashSet, VecDeque};
+use std::hash::Hash;
 
 struct TileEmpireExtension;
 
@@ -23,6 +24,16 @@ impl HexMath {
         ((ax - bx).abs() + (ax + ay - bx - by).abs() + (ay - by).abs()) / 2
     }
 
+    /// Hex neighbors of an axial (q, r) tile, in the same order as `hex_neighbors`.
+    #[func]
+    fn hex_neighbors_axial(axial: Vector2i) -> Array<Vector2i> {
+        let mut result = Array::new();
+        for (q, r) in axial_neighbors((axial.x, axial.y)) {
+            result.push(Vector2i::new(q, r));
+        }
+        result
+    }
+
     /// Get hex neighbors for odd-q offset coordinates.
     #[func]
     fn hex_neighbors(pos: Vector2i) -> Array<Vector2i> {
@@ -40,549 +51,8006 @@ impl HexMath {
         result
     }
 
-    /// A* pathfinding on a hex grid. Returns array of Vector2i positions.
-    /// `blocked` is an array of impassable positions.
-    /// `costs` is a Dictionary mapping Vector2i -> float movement cost (default 1.0).
-    /// Returns empty array if no path found.
+    /// Cube coordinates (x + y + z == 0) of an odd-q offset tile.
     #[func]
-    fn find_path(
+    fn offset_to_cube(pos: Vector2i) -> Vector3i {
+        let (q, r) = to_axial(pos);
+        Vector3i::new(q, r, -q - r)
+    }
+
+    /// Odd-q offset tile of cube coordinates. Coordinates that do not sum to zero
+    /// are snapped to a valid cube first via `cube_round`.
+    #[func]
+    fn cube_to_offset(cube: Vector3i) -> Vector2i {
+        let (q, r, _) = if cube.x + cube.y + cube.z == 0 {
+            (cube.x, cube.y, cube.z)
+        } else {
+            cube_round(cube.x as f64, cube.y as f64, cube.z as f64)
+        };
+        let (x, y) = from_axial(q, r);
+        Vector2i::new(x, y)
+    }
+
+    /// Unweighted step distance from `from` to `to` walking around `blocked` tiles
+    /// within the map, or -1 if `to` cannot be reached.
+    #[func]
+    fn path_distance(
         from: Vector2i,
         to: Vector2i,
         blocked: Array<Vector2i>,
-        costs: Dictionary<Vector2i, f64>,
-        max_distance: i32,
-    ) -> Array<Vector2i> {
-        use std::collections::{HashMap, HashSet};
-
-        let blocked_set: HashSet<(i32, i32)> = blocked.iter_shared().map(|v| (v.x, v.y)).collect();
-
-        if blocked_set.contains(&(to.x, to.y)) {
-            return Array::new();
-        }
-
-        #[derive(Clone)]
-        struct Node {
-            pos: (i32, i32),
-            g: f64,
-            f: f64,
-        }
-
-        impl PartialEq for Node {
-            fn eq(&self, other: &Self) -> bool {
-                self.f == other.f
-            }
-        }
-        impl Eq for Node {}
-        impl PartialOrd for Node {
-            fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-                Some(self.cmp(other))
-            }
-        }
-        impl Ord for Node {
-            fn cmp(&self, other: &Self) -> Ordering {
-                other.f.partial_cmp(&self.f).unwrap_or(Ordering::Equal)
-            }
-        }
-
-        let mut open = BinaryHeap::new();
-        let mut came_from: HashMap<(i32, i32), (i32, i32)> = HashMap::new();
-        let mut g_scores: HashMap<(i32, i32), f64> = HashMap::new();
-
-        let start = (from.x, from.y);
-        let goal = (to.x, to.y);
-
-        g_scores.insert(start, 0.0);
-        let h = Self::hex_distance(from, to) as f64;
-        open.push(Node {
-            pos: start,
-            g: 0.0,
-            f: h,
+        map_width: i32,
+        map_height: i32,
+    ) -> i32 {
+        let Some(ti) = grid_index((to.x, to.y), map_width, map_height) else {
+            return -1;
+        };
+        let blocked_mask = blocked_grid(&blocked, map_width, map_height);
+        let dist = bfs_distance_grid(&[(from.x, from.y)], map_width, map_height, |i| {
+            !blocked_mask[i]
         });
+        dist[ti]
+    }
 
-        while let Some(current) = open.pop() {
-            if current.pos == goal {
-                // Reconstruct path
-                let mut path = Vec::new();
-                let mut cur = goal;
-                while cur != start {
-                    path.push(Vector2i::new(cur.0, cur.1));
-                    cur = came_from[&cur];
-                }
-                path.push(Vector2i::new(start.0, start.1));
-                path.reverse();
-                let mut result = Array::new();
-                for p in path {
-                    result.push(p);
-                }
-                return result;
-            }
-
-            let current_g = *g_scores.get(&current.pos).unwrap_or(&f64::MAX);
-            if current.g > current_g {
-                continue;
-            }
-
-            let pos_v = Vector2i::new(current.pos.0, current.pos.1);
-            let neighbors = Self::hex_neighbors(pos_v);
-
-            for n in neighbors.iter_shared() {
-                let np = (n.x, n.y);
-                if blocked_set.contains(&np) {
-                    continue;
-                }
+    /// Retreat path for a wounded unit: among tiles reachable within `steps` moves
+    /// (avoiding `blocked`), pick the one with the highest `influence` (the unit owner's
+    /// net influence grid, row-major) and return the path from `from` to it.
+    /// Ties prefer fewer steps. Returns `[from]` if staying put is safest.
+    #[func]
+    fn find_retreat(
+        from: Vector2i,
+        influence: PackedFloat32Array,
+        blocked: Array<Vector2i>,
+        steps: i32,
+        map_width: i32,
+        map_height: i32,
+    ) -> Array<Vector2i> {
+        let blocked_mask = blocked_grid(&blocked, map_width, map_height);
+        path_to_array(retreat_path(
+            (from.x, from.y),
+            influence.as_slice(),
+            &blocked_mask,
+            steps,
+            map_width,
+            map_height,
+        ))
+    }
 
-                let dist_from_start = Self::hex_distance(from, Vector2i::new(np.0, np.1));
-                if dist_from_start > max_distance {
-                    continue;
-                }
+    /// The tile in `candidates` nearest to `target` by hex distance, ties to the
+    /// earliest in the array. Vector2i(-1, -1) if `candidates` is empty.
+    #[func]
+    fn closest_in_set(target: Vector2i, candidates: Array<Vector2i>) -> Vector2i {
+        let candidates: Vec<(i32, i32)> = candidates.iter_shared().map(|v| (v.x, v.y)).collect();
+        let (x, y) = closest_tile((target.x, target.y), &candidates).unwrap_or((-1, -1));
+        Vector2i::new(x, y)
+    }
 
-                let cost: f64 = costs.get(n).unwrap_or(1.0);
+    /// Smallest offset-coordinate rectangle containing every tile in `tiles`: `position`
+    /// is the min corner and `size` spans through the max tile inclusive, so a single
+    /// tile gives size (1, 1). Empty input gives a zero-size Rect2i at the origin.
+    #[func]
+    fn bounding_box(tiles: Array<Vector2i>) -> Rect2i {
+        let tiles: Vec<(i32, i32)> = tiles.iter_shared().map(|v| (v.x, v.y)).collect();
+        bounding_rect(&tiles)
+    }
 
-                let tentative_g = current_g + cost;
-                let prev_g = *g_scores.get(&np).unwrap_or(&f64::MAX);
-                if tentative_g < prev_g {
-                    came_from.insert(np, current.pos);
-                    g_scores.insert(np, tentative_g);
-                    let h = Self::hex_distance(Vector2i::new(np.0, np.1), to) as f64;
-                    open.push(Node {
-                        pos: np,
-                        g: tentative_g,
-                        f: tentative_g + h,
-                    });
-                }
-            }
+    /// Deduplicated in-bounds hex neighbors of every tile in `tiles`, excluding the
+    /// input tiles themselves: the frontier of an arbitrary tile set.
+    #[func]
+    fn expand_region(tiles: Array<Vector2i>, map_width: i32, map_height: i32) -> Array<Vector2i> {
+        let region: Vec<(i32, i32)> = tiles.iter_shared().map(|v| (v.x, v.y)).collect();
+        let mut result = Array::new();
+        for (x, y) in region_frontier(&region, map_width, map_height) {
+            result.push(Vector2i::new(x, y));
         }
+        result
+    }
 
-        Array::new() // No path found
+    /// Pixel position of the center of an odd-q (flat-top) tile. `layout_size` is the
+    /// hex radius along each axis, so x and y can be scaled independently for
+    /// squashed or stretched art; `origin` is the pixel position of tile (0, 0).
+    #[func]
+    fn hex_to_pixel(pos: Vector2i, layout_size: Vector2, origin: Vector2) -> Vector2 {
+        hex_center(pos, layout_size, origin)
     }
-}
 
-/// Convert odd-q offset to axial coordinates.
-fn to_axial(pos: Vector2i) -> (i32, i32) {
-    let x = pos.x;
-    let y = pos.y - (pos.x - (pos.x & 1)) / 2;
-    (x, y)
-}
+    /// Tile containing `pixel`; the inverse of `hex_to_pixel` for the same layout.
+    #[func]
+    fn pixel_to_hex(pixel: Vector2, layout_size: Vector2, origin: Vector2) -> Vector2i {
+        let (x, y) = pixel_tile(pixel, layout_size, origin);
+        Vector2i::new(x, y)
+    }
 
-/// Get hex neighbors for odd-q offset coordinates (standalone helper).
-fn hex_neighbors_vec(x: i32, y: i32) -> [(i32, i32); 6] {
-    if x & 1 == 0 {
-        [
-            (x + 1, y),
-            (x + 1, y - 1),
-            (x, y - 1),
-            (x - 1, y - 1),
-            (x - 1, y),
-            (x, y + 1),
-        ]
-    } else {
-        [
-            (x + 1, y + 1),
-            (x + 1, y),
-            (x, y - 1),
-            (x - 1, y),
-            (x - 1, y + 1),
-            (x, y + 1),
-        ]
+    /// The six corner pixels of a tile, starting at the right-hand corner and going
+    /// clockwise on screen (y down), for drawing outlines with the same layout as
+    /// `hex_to_pixel`.
+    #[func]
+    fn hex_corners(pos: Vector2i, layout_size: Vector2, origin: Vector2) -> PackedVector2Array {
+        let center = hex_center(pos, layout_size, origin);
+        let corners: Vec<Vector2> = (0..6)
+            .map(|i| {
+                let angle = (60.0 * i as f32).to_radians();
+                Vector2::new(
+                    center.x + layout_size.x * angle.cos(),
+                    center.y + layout_size.y * angle.sin(),
+                )
+            })
+            .collect();
+        PackedVector2Array::from(corners.as_slice())
     }
-}
 
-// ============================================================
-// 1. InfluenceMap
-// ============================================================
+    /// Point a fraction `t` of the way along the straight pixel-space line between the
+    /// centers of `from` and `to` (same layout as `hex_to_pixel`, with tile (0, 0) at
+    /// the origin), e.g. for animating projectiles. Unlike `hex_lerp` the result is
+    /// not snapped to a tile; `t` outside [0, 1] extrapolates.
+    #[func]
+    fn pixel_lerp(from: Vector2i, to: Vector2i, t: f64, layout_size: Vector2) -> Vector2 {
+        let a = hex_center(from, layout_size, Vector2::ZERO);
+        let b = hex_center(to, layout_size, Vector2::ZERO);
+        Vector2::new(
+            (a.x as f64 + (b.x - a.x) as f64 * t) as f32,
+            (a.y as f64 + (b.y - a.y) as f64 * t) as f32,
+        )
+    }
 
-#[derive(GodotClass)]
-#[class(base=RefCounted, init)]
-pub struct InfluenceMap {
-    #[allow(dead_code)]
-    influence: Vec<Vec<f32>>, // per-player influence grids
-    width: usize,
-    height: usize,
-    num_players: usize,
-}
+    /// Angle in radians (`atan2`, screen coordinates with y down) of the pixel-space
+    /// vector from the center of `from` to the center of `to`, e.g. for aiming turrets.
+    /// Unlike `direction_between` this is continuous and works for any pair of tiles;
+    /// 0 is due east and the result lies in (-PI, PI], 0 for `from == to`.
+    #[func]
+    fn bearing(from: Vector2i, to: Vector2i, layout_size: Vector2) -> f32 {
+        let delta = hex_center(to, layout_size, Vector2::ZERO)
+            - hex_center(from, layout_size, Vector2::ZERO);
+        delta.y.atan2(delta.x)
+    }
 
-#[godot_api]
-impl InfluenceMap {
-    /// Compute influence for all players.
-    /// unit_positions_by_player: Dictionary { player_id: int -> Array[Vector2i] of grid positions }
-    /// territory_owner_grid: PackedInt32Array of size w*h, row-major, value = owner or -1
+    /// Convex hull of the tile centers of `tiles` (same layout as `hex_to_pixel`, with
+    /// tile (0, 0) at the origin), clockwise on screen from the leftmost center, with
+    /// collinear points dropped. Degenerate sets give minimal hulls: empty for no
+    /// tiles, one point for a single tile, and the two end centers for a straight line.
     #[func]
-    fn compute(
-        &mut self,
-        unit_positions_by_player: Dictionary<Variant, Variant>,
-        territory_owner_grid: PackedInt32Array,
+    fn convex_hull(tiles: Array<Vector2i>, layout_size: Vector2) -> PackedVector2Array {
+        let tiles: Vec<(i32, i32)> = tiles.iter_shared().map(|v| (v.x, v.y)).collect();
+        let half_height = 3f64.sqrt() / 2.0;
+        let points: Vec<Vector2> = hull_lattice(&tiles)
+            .into_iter()
+            .map(|(x, y)| {
+                Vector2::new(
+                    (layout_size.x as f64 * x as f64 / 2.0) as f32,
+                    (layout_size.y as f64 * y as f64 * half_height) as f32,
+                )
+            })
+            .collect();
+        PackedVector2Array::from(points.as_slice())
+    }
+
+    /// In-bounds tiles within `sight_range` of `from` that it can see: no tile strictly
+    /// between them on the line (as in `HexLOS.cover_fraction`) has a type in
+    /// `blocking_types`. Blocking tiles themselves can be seen. Ordered by distance
+    /// from `from` (ring by ring), starting with `from`; empty if `from` is off the map.
+    #[func]
+    fn hexes_in_los(
+        from: Vector2i,
+        sight_range: i32,
+        tile_types: PackedInt32Array,
+        blocking_types: PackedInt32Array,
         map_width: i32,
         map_height: i32,
-    ) {
-        let w = map_width as usize;
-        let h = map_height as usize;
-        self.width = w;
-        self.height = h;
+    ) -> Array<Vector2i> {
+        let visible = visible_tiles(
+            (from.x, from.y),
+            sight_range,
+            tile_types.as_slice(),
+            blocking_types.as_slice(),
+            map_width,
+            map_height,
+        );
+        path_to_array(Some(visible))
+    }
 
-        // Determine number of players
-        let mut max_pid: i32 = -1;
-        for key in unit_positions_by_player.keys_array().iter_shared() {
-            let pid = i32::from_variant(&key);
-            if pid > max_pid {
-                max_pid = pid;
-            }
-        }
-        for i in 0..territory_owner_grid.len() {
-            let v = territory_owner_grid[i];
-            if v > max_pid {
-                max_pid = v;
-            }
+    /// Blast damage around `center`: every in-bounds tile within `radius` maps to
+    /// `max(0, base_damage - falloff_per_ring * ring)`, where `ring` is its hex
+    /// distance from `center`. Dictionary { Vector2i -> int }.
+    #[func]
+    fn blast_damage(
+        center: Vector2i,
+        base_damage: i32,
+        radius: i32,
+        falloff_per_ring: i32,
+        map_width: i32,
+        map_height: i32,
+    ) -> Dictionary<Vector2i, i32> {
+        let mut result = Dictionary::new();
+        for ((x, y), damage) in blast_damage_tiles(
+            (center.x, center.y),
+            base_damage,
+            radius,
+            falloff_per_ring,
+            map_width,
+            map_height,
+        ) {
+            result.set(Vector2i::new(x, y), damage);
         }
-        let np = (max_pid + 1).max(0) as usize;
-        self.num_players = np;
-
-        // Raw per-player influence
-        let mut raw: Vec<Vec<f32>> = vec![vec![0.0; w * h]; np];
+        result
+    }
 
-        let sigma: f32 = 4.0;
-        let two_sigma_sq = 2.0 * sigma * sigma;
-        let max_range = (sigma * 3.0) as i32; // cutoff at 3 sigma
+    /// Tile at fraction `t` (0.0 = `a`, 1.0 = `b`) along the straight line from `a`
+    /// to `b`, e.g. for partial-move previews. `t` outside [0, 1] extrapolates.
+    #[func]
+    fn hex_lerp(a: Vector2i, b: Vector2i, t: f64) -> Vector2i {
+        let (x, y) = lerp_tile(a, b, t);
+        Vector2i::new(x, y)
+    }
 
-        // Add unit influence
-        for key in unit_positions_by_player.keys_array().iter_shared() {
-            let pid = i32::from_variant(&key) as usize;
-            if pid >= np {
-                continue;
-            }
-            let val_variant = unit_positions_by_player.get(&key).unwrap();
-            let positions: Array<Vector2i> = Array::from_variant(&val_variant);
-            for pos in positions.iter_shared() {
-                let cx = pos.x;
-                let cy = pos.y;
-                for dy in -max_range..=max_range {
-                    for dx in -max_range..=max_range {
-                        let nx = cx + dx;
-                        let ny = cy + dy;
-                        if nx < 0 || ny < 0 || nx >= w as i32 || ny >= h as i32 {
-                            continue;
-                        }
-                        let dist_sq = (dx * dx + dy * dy) as f32;
-                        let val = 2.0 * (-dist_sq / two_sigma_sq).exp();
-                        raw[pid][ny as usize * w + nx as usize] += val;
-                    }
-                }
-            }
+    /// Tiles on the straight line from `from` to `to`, endpoints included, with each
+    /// consecutive pair adjacent. Symmetric: `hex_line(b, a)` is `hex_line(a, b)`
+    /// reversed.
+    #[func]
+    fn hex_line(from: Vector2i, to: Vector2i) -> Array<Vector2i> {
+        let mut result = Array::new();
+        for (x, y) in hex_line_xy(from, to) {
+            result.push(Vector2i::new(x, y));
         }
+        result
+    }
 
-        // Add territory influence
-        for i in 0..territory_owner_grid.len() {
-            let owner = territory_owner_grid[i];
-            if owner < 0 || owner as usize >= np {
-                continue;
-            }
-            let cx = (i % w) as i32;
-            let cy = (i / w) as i32;
-            let pid = owner as usize;
-            for dy in -max_range..=max_range {
-                for dx in -max_range..=max_range {
-                    let nx = cx + dx;
-                    let ny = cy + dy;
-                    if nx < 0 || ny < 0 || nx >= w as i32 || ny >= h as i32 {
-                        continue;
-                    }
-                    let dist_sq = (dx * dx + dy * dy) as f32;
-                    let val = 0.5 * (-dist_sq / two_sigma_sq).exp();
-                    raw[pid][ny as usize * w + nx as usize] += val;
-                }
-            }
-        }
+    /// Position of `pos` in the hex spiral around tile (0, 0): the origin is 0, its six
+    /// neighbors are 1-6 in `hex_neighbors` order, then ring 2, and so on. Each ring
+    /// starts at its direction-0 corner and runs in increasing direction order.
+    #[func]
+    fn spiral_index(pos: Vector2i) -> i32 {
+        spiral_index_axial(to_axial(pos))
+    }
 
-        // Net influence = own - max(enemies)
-        self.influence = Vec::with_capacity(np);
-        for pid in 0..np {
-            let mut net = vec![0.0f32; w * h];
-            for i in 0..w * h {
-                let own = raw[pid][i];
-                let mut max_enemy = 0.0f32;
-                for (other, raw_other) in raw.iter().enumerate().take(np) {
-                    if other != pid {
-                        max_enemy = max_enemy.max(raw_other[i]);
-                    }
-                }
-                net[i] = own - max_enemy;
-            }
-            self.influence.push(net);
-        }
+    /// Inverse of `spiral_index`. Negative indices map to the origin.
+    #[func]
+    fn from_spiral_index(index: i32) -> Vector2i {
+        let (q, r) = spiral_axial(index);
+        let (x, y) = from_axial(q, r);
+        Vector2i::new(x, y)
     }
 
+    /// Tiles at exactly `radius` from `center`, walking the ring clockwise on screen
+    /// starting from the corner in direction 4. Radius 0 returns `[center]`.
     #[func]
-    fn get_player_influence(&self, player_id: i32) -> PackedFloat32Array {
-        let pid = player_id as usize;
-        if pid < self.influence.len() {
-            PackedFloat32Array::from(self.influence[pid].as_slice())
-        } else {
-            PackedFloat32Array::new()
-        }
+    fn hex_ring(center: Vector2i, radius: i32) -> Array<Vector2i> {
+        path_to_array(Some(ring_segment_vec((center.x, center.y), radius, 4, 4)))
     }
-}
 
-// ============================================================
-// 2. TerritoryFrontier
-// ============================================================
+    /// Contiguous arc of the ring of `radius` around `center`, from the corner in
+    /// direction `start_dir` to the corner in direction `end_dir` inclusive, walking
+    /// clockwise on screen, i.e. in decreasing direction order (wrapping from 0 to 5
+    /// when `end_dir > start_dir`). Equal directions return the whole ring starting at
+    /// that corner.
+    #[func]
+    fn hex_ring_segment(
+        center: Vector2i,
+        radius: i32,
+        start_dir: i32,
+        end_dir: i32,
+    ) -> Array<Vector2i> {
+        path_to_array(Some(ring_segment_vec(
+            (center.x, center.y),
+            radius,
+            start_dir,
+            end_dir,
+        )))
+    }
 
-#[derive(GodotClass)]
-#[class(base=RefCounted, init)]
-pub struct TerritoryFrontier;
+    /// Flat row-major indices of the six neighbors of `pos`, in `hex_neighbors` order.
+    /// Out-of-bounds neighbors are -1.
+    #[func]
+    fn neighbor_indices(pos: Vector2i, map_width: i32, map_height: i32) -> PackedInt32Array {
+        let indices = neighbor_indices_vec(pos.x, pos.y, map_width, map_height);
+        PackedInt32Array::from(indices.as_slice())
+    }
 
-#[godot_api]
-impl TerritoryFrontier {
-    /// Returns Array[Vector2i] of frontier tiles (adjacent to player's territory, not owned by player, not water=3).
+    /// A* pathfinding on a hex grid. Returns array of Vector2i positions.
+    /// `blocked` is an array of impassable positions.
+    /// `costs` is a Dictionary mapping Vector2i -> float movement cost (default 1.0).
+    /// Returns empty array if no path found.
     #[func]
-    fn get_frontier(
-        &self,
-        owner_grid: PackedInt32Array,
-        player_id: i32,
+    fn find_path(
+        from: Vector2i,
+        to: Vector2i,
+        blocked: Array<Vector2i>,
+        costs: Dictionary<Vector2i, f64>,
+        max_distance: i32,
+    ) -> Array<Vector2i> {
+        let grid = PathGrid::new(&blocked, &costs, max_distance);
+        path_to_array(grid.find_path((from.x, from.y), (to.x, to.y)))
+    }
+
+    /// Road network tying `cities` (grid coordinates, rounded) together: a minimum
+    /// spanning tree over the cheapest path cost between each pair of cities (entry
+    /// costs from `costs`, default 1.0, staying on the map and off `blocked`), with
+    /// the tiles of its paths unioned. Cities off the map, on `blocked`, or that
+    /// cannot be reached from the first remaining one are left out. Tiles come path
+    /// by path in the order the tree grows.
+    #[func]
+    fn connect_cities(
+        cities: PackedVector2Array,
+        costs: Dictionary<Vector2i, f64>,
+        blocked: Array<Vector2i>,
         map_width: i32,
         map_height: i32,
     ) -> Array<Vector2i> {
-        let w = map_width as usize;
-        let h = map_height as usize;
-        let mut frontier_set = std::collections::HashSet::new();
-        let mut result = Array::new();
+        let mut grid = PathGrid::new(&blocked, &costs, map_width + map_height);
+        grid.blocked
+            .extend(impassable_tiles(&[], &[], map_width, map_height));
+        let cities: Vec<(i32, i32)> = cities
+            .as_slice()
+            .iter()
+            .map(|p| (p.x.round() as i32, p.y.round() as i32))
+            .filter(|&c| grid_index(c, map_width, map_height).is_some())
+            .collect();
+        path_to_array(Some(grid.road_network(&cities)))
+    }
+
+    /// Many `find_path` queries sharing one blocked/cost setup, for per-turn AI move
+    /// orders. `requests` is an Array of `[from: Vector2i, to: Vector2i]` pairs; the
+    /// result holds one Array[Vector2i] path per request, in order, each identical
+    /// to the standalone `find_path` result (empty if unreachable or malformed).
+    #[func]
+    fn find_paths_batch(
+        requests: Array<Variant>,
+        blocked: Array<Vector2i>,
+        costs: Dictionary<Vector2i, f64>,
+        max_distance: i32,
+    ) -> Array<Variant> {
+        let grid = PathGrid::new(&blocked, &costs, max_distance);
+        let requests: Vec<_> = requests.iter_shared().map(|r| path_request(&r)).collect();
+        let mut result = Array::new();
+        for path in grid.find_paths(&requests) {
+            result.push(&Variant::from(path_to_array(path)));
+        }
+        result
+    }
+
+    /// Up to `k` cheapest routes, e.g. a primary and a backup, by Yen's algorithm on
+    /// top of `find_path`: distinct loopless paths in non-decreasing cost order. Paths
+    /// differ in at least one step but may share tiles, so on open ground the next
+    /// route is often a small variation of the previous one. Fewer than `k` are
+    /// returned when fewer routes exist. Returns an Array of Array[Vector2i].
+    #[func]
+    fn find_k_paths(
+        from: Vector2i,
+        to: Vector2i,
+        k: i32,
+        blocked: Array<Vector2i>,
+        costs: Dictionary<Vector2i, f64>,
+        max_distance: i32,
+    ) -> Array<Variant> {
+        let grid = PathGrid::new(&blocked, &costs, max_distance);
+        let mut result = Array::new();
+        for path in grid.find_k_shortest_paths((from.x, from.y), (to.x, to.y), k.max(0) as usize) {
+            result.push(&Variant::from(path_to_array(Some(path))));
+        }
+        result
+    }
+
+    /// `find_path` with movement costs computed on demand: `cost_fn(tile: Vector2i) -> float`
+    /// is called at most once per tile, and only for tiles the search considers
+    /// entering. Each call crosses into the script runtime, so this is slower per tile
+    /// than a `costs` Dictionary; prefer it when costs combine several factors and
+    /// the search touches a small part of a large map, where building the full
+    /// Dictionary up front would cost more. Non-numeric results count as 1.0.
+    #[func]
+    fn find_path_callable(
+        from: Vector2i,
+        to: Vector2i,
+        blocked: Array<Vector2i>,
+        cost_fn: Callable,
+        max_distance: i32,
+    ) -> Array<Vector2i> {
+        let grid = PathGrid::new(&blocked, &Dictionary::new(), max_distance);
+        let path = grid.find_path_with((from.x, from.y), (to.x, to.y), |(x, y)| {
+            let cost = cost_fn.call(&[Variant::from(Vector2i::new(x, y))]);
+            cost.try_to::<f64>()
+                .or_else(|_| cost.try_to::<i64>().map(|c| c as f64))
+                .unwrap_or(1.0)
+        });
+        path_to_array(path)
+    }
+
+    /// `find_path` plus what the search explored, for visualizing heuristics:
+    /// Dictionary { "path": Array[Vector2i], "expanded": Array[Vector2i] in expansion
+    /// order, "g_scores": Dictionary { Vector2i -> float } for every tile reached }.
+    /// "path" is exactly what `find_path` returns.
+    #[func]
+    fn find_path_debug(
+        from: Vector2i,
+        to: Vector2i,
+        blocked: Array<Vector2i>,
+        costs: Dictionary<Vector2i, f64>,
+        max_distance: i32,
+    ) -> Dictionary<Variant, Variant> {
+        let grid = PathGrid::new(&blocked, &costs, max_distance);
+        let mut trace = SearchTrace::default();
+        let path = grid.find_path_traced(
+            (from.x, from.y),
+            (to.x, to.y),
+            SearchLimits::default(),
+            Some(&mut trace),
+        );
+        let mut expanded = Array::<Vector2i>::new();
+        for (x, y) in trace.expanded {
+            expanded.push(Vector2i::new(x, y));
+        }
+        let mut g_scores = Dictionary::<Variant, Variant>::new();
+        for ((x, y), g) in trace.g_scores {
+            g_scores.set(&Variant::from(Vector2i::new(x, y)), &Variant::from(g));
+        }
+        let mut result = Dictionary::new();
+        result.set(&Variant::from("path"), &Variant::from(path_to_array(path)));
+        result.set(&Variant::from("expanded"), &Variant::from(expanded));
+        result.set(&Variant::from("g_scores"), &Variant::from(g_scores));
+        result
+    }
+
+    /// `find_path` for callers that store their map in axial coordinates: `from`, `to`,
+    /// `blocked`, the `costs` keys and the returned path are all axial (q, r), so no
+    /// offset conversion happens anywhere.
+    #[func]
+    fn find_path_axial(
+        from: Vector2i,
+        to: Vector2i,
+        blocked: Array<Vector2i>,
+        costs: Dictionary<Vector2i, f64>,
+        max_distance: i32,
+    ) -> Array<Vector2i> {
+        let grid = PathGrid::new(&blocked, &costs, max_distance);
+        path_to_array(grid.find_path_axial((from.x, from.y), (to.x, to.y)))
+    }
+
+    /// Tiles reachable from `from` for a total movement cost of at most `budget`
+    /// (within `max_distance` hexes), cheapest first, `from` included. With `axial`
+    /// set, all positions in and out (including `blocked` and the `costs` keys) are
+    /// axial instead of odd-q offset.
+    #[func]
+    fn reachable_tiles(
+        from: Vector2i,
+        blocked: Array<Vector2i>,
+        costs: Dictionary<Vector2i, f64>,
+        max_distance: i32,
+        budget: f64,
+        axial: bool,
+    ) -> Array<Vector2i> {
+        let grid = PathGrid::new(&blocked, &costs, max_distance);
+        path_to_array(Some(grid.reachable((from.x, from.y), budget, axial)))
+    }
+
+    /// Where a land unit at `from` can get this turn with the help of friendly
+    /// transports on water (`transports`, grid coordinates, rounded). Simplifying
+    /// assumptions: land moves cost 1 per tile over non-water (type 3) tiles; the unit
+    /// may board one transport whose tile is adjacent to a land tile it can reach
+    /// within `land_budget` (boarding is free and ends its land movement); the
+    /// transport then sails up to `sea_budget` water tiles at 1 per tile. No second
+    /// embark or same-turn landing. Returns Dictionary { "land": Array[Vector2i]
+    /// cheapest first, "sea": Array[Vector2i] row-major, "transports":
+    /// PackedInt32Array of boardable transport indices }.
+    #[func]
+    fn amphibious_reachable(
+        from: Vector2i,
+        transports: PackedVector2Array,
+        land_budget: f64,
+        sea_budget: f64,
+        tile_types: PackedInt32Array,
+        map_width: i32,
+        map_height: i32,
+    ) -> Dictionary<Variant, Variant> {
+        let transports: Vec<(i32, i32)> = transports
+            .as_slice()
+            .iter()
+            .map(|p| (p.x.round() as i32, p.y.round() as i32))
+            .collect();
+        let (land, sea, boardable) = amphibious_tiles(
+            (from.x, from.y),
+            &transports,
+            land_budget,
+            sea_budget,
+            tile_types.as_slice(),
+            map_width,
+            map_height,
+        );
+        let mut result = Dictionary::new();
+        result.set(
+            &Variant::from("land"),
+            &Variant::from(path_to_array(Some(land))),
+        );
+        result.set(
+            &Variant::from("sea"),
+            &Variant::from(path_to_array(Some(sea))),
+        );
+        result.set(
+            &Variant::from("transports"),
+            &Variant::from(PackedInt32Array::from(boardable.as_slice())),
+        );
+        result
+    }
+
+    /// Safe destinations: tiles reachable from `from` within `budget` (as in
+    /// `reachable_tiles`, staying on the map) that are outside every enemy's zone of
+    /// control, i.e. neither an `enemy_positions` tile (grid coordinates, rounded)
+    /// nor adjacent to one. Cheapest first; `from` is included only if it is safe.
+    #[func]
+    fn safe_moves(
+        from: Vector2i,
+        enemy_positions: PackedVector2Array,
+        blocked: Array<Vector2i>,
+        costs: Dictionary<Vector2i, f64>,
+        budget: f64,
+        map_width: i32,
+        map_height: i32,
+    ) -> Array<Vector2i> {
+        let mut grid = PathGrid::new(&blocked, &costs, i32::MAX);
+        grid.blocked
+            .extend(impassable_tiles(&[], &[], map_width, map_height));
+        let enemies: Vec<(i32, i32)> = enemy_positions
+            .as_slice()
+            .iter()
+            .map(|p| (p.x.round() as i32, p.y.round() as i32))
+            .collect();
+        path_to_array(Some(safe_destinations(
+            &grid,
+            (from.x, from.y),
+            &enemies,
+            budget,
+        )))
+    }
+
+    /// Tiles a ranged unit at `from` can move to this turn (total cost within `budget`,
+    /// staying on the map) and attack `target` from: within `attack_range` hexes of it and with clear
+    /// line of sight as in `HexLOS.has_line_of_sight`. Cheapest tiles first.
+    #[func]
+    #[allow(clippy::too_many_arguments)]
+    fn firing_positions(
+        from: Vector2i,
+        target: Vector2i,
+        attack_range: i32,
+        blocked: Array<Vector2i>,
+        costs: Dictionary<Vector2i, f64>,
+        budget: f64,
+        tile_types: PackedInt32Array,
+        map_width: i32,
+        map_height: i32,
+    ) -> Array<Vector2i> {
+        let mut grid = PathGrid::new(&blocked, &costs, i32::MAX);
+        grid.blocked
+            .extend(impassable_tiles(&[], &[], map_width, map_height));
+        let tiles = firing_tiles(
+            &grid,
+            (from.x, from.y),
+            (target.x, target.y),
+            attack_range,
+            budget,
+            tile_types.as_slice(),
+            map_width,
+            map_height,
+        );
+        path_to_array(Some(tiles))
+    }
+
+    /// `find_path` with a choice of ordering: goal-first when `reverse` is true,
+    /// start-first (the same as `find_path`) otherwise.
+    #[func]
+    fn find_path_ordered(
+        from: Vector2i,
+        to: Vector2i,
+        blocked: Array<Vector2i>,
+        costs: Dictionary<Vector2i, f64>,
+        max_distance: i32,
+        reverse: bool,
+    ) -> Array<Vector2i> {
+        let grid = PathGrid::new(&blocked, &costs, max_distance);
+        path_to_array(grid.find_path_ordered((from.x, from.y), (to.x, to.y), reverse))
+    }
+
+    /// Server-side check of a claimed move: every tile is in bounds and not blocked,
+    /// each consecutive pair is adjacent, and the summed entry cost of every tile
+    /// after the first (`costs`, default 1.0) is within `budget`. An empty path is
+    /// invalid.
+    #[func]
+    fn is_path_valid(
+        path: Array<Vector2i>,
+        blocked: Array<Vector2i>,
+        costs: Dictionary<Vector2i, f64>,
+        budget: f64,
+        map_width: i32,
+        map_height: i32,
+    ) -> bool {
+        let grid = PathGrid::new(&blocked, &costs, i32::MAX);
+        let tiles: Vec<(i32, i32)> = path.iter_shared().map(|v| (v.x, v.y)).collect();
+        grid.is_path_valid(&tiles, budget, map_width, map_height)
+    }
+
+    /// Next move along a cached `path` for a unit at `current`: the tile after
+    /// `current`'s (first) position in `path`, or `current` itself once it is the
+    /// last tile. Returns Vector2i(-1, -1) when the path must be recomputed: the
+    /// next tile is now in `blocked`, or `current` is not on the path.
+    #[func]
+    fn next_step(path: Array<Vector2i>, current: Vector2i, blocked: Array<Vector2i>) -> Vector2i {
+        let path: Vec<(i32, i32)> = path.iter_shared().map(|v| (v.x, v.y)).collect();
+        let blocked: HashSet<(i32, i32)> = blocked.iter_shared().map(|v| (v.x, v.y)).collect();
+        let (x, y) = path_next_step(&path, (current.x, current.y), &blocked).unwrap_or((-1, -1));
+        Vector2i::new(x, y)
+    }
+
+    /// Like `find_path`, but also rejects paths longer than `max_steps` moves.
+    /// `max_distance` still bounds how far from `from` the search may wander.
+    #[func]
+    fn find_path_max_steps(
+        from: Vector2i,
+        to: Vector2i,
+        blocked: Array<Vector2i>,
+        costs: Dictionary<Vector2i, f64>,
+        max_distance: i32,
+        max_steps: i32,
+    ) -> Array<Vector2i> {
+        let grid = PathGrid::new(&blocked, &costs, max_distance);
+        let limits = SearchLimits {
+            max_steps: max_steps.max(0) as usize,
+            ..Default::default()
+        };
+        path_to_array(grid.find_path_limited((from.x, from.y), (to.x, to.y), limits))
+    }
+
+    /// Like `find_path`, but prunes any tile whose accumulated movement cost exceeds
+    /// `max_cost` (e.g. a unit's remaining movement points). `max_distance` still
+    /// applies; a tile must satisfy both bounds.
+    #[func]
+    fn find_path_max_cost(
+        from: Vector2i,
+        to: Vector2i,
+        blocked: Array<Vector2i>,
+        costs: Dictionary<Vector2i, f64>,
+        max_distance: i32,
+        max_cost: f64,
+    ) -> Array<Vector2i> {
+        let grid = PathGrid::new(&blocked, &costs, max_distance);
+        let limits = SearchLimits {
+            max_cost,
+            ..Default::default()
+        };
+        path_to_array(grid.find_path_limited((from.x, from.y), (to.x, to.y), limits))
+    }
+
+    /// Like `find_path`, but gives up after expanding `max_expansions` tiles so one call
+    /// cannot blow the frame budget. On abort it returns an empty array, the same as
+    /// when no path exists; callers can retry with a larger budget or fall back.
+    #[func]
+    fn find_path_bounded(
+        from: Vector2i,
+        to: Vector2i,
+        blocked: Array<Vector2i>,
+        costs: Dictionary<Vector2i, f64>,
+        max_distance: i32,
+        max_expansions: i32,
+    ) -> Array<Vector2i> {
+        let grid = PathGrid::new(&blocked, &costs, max_distance);
+        let limits = SearchLimits {
+            max_expansions: max_expansions.max(0) as usize,
+            ..Default::default()
+        };
+        path_to_array(grid.find_path_limited((from.x, from.y), (to.x, to.y), limits))
+    }
+
+    /// Picks the goal maximizing `goal_value - travel_cost` among `goals` (parallel
+    /// to `goal_values`) and paths to it, so a near modest goal can beat a far
+    /// valuable one. Returns Dictionary { "path": Array[Vector2i], "goal": Vector2i,
+    /// "score": float }, or an empty Dictionary if no goal is reachable.
+    #[func]
+    fn find_path_best_goal(
+        from: Vector2i,
+        goals: Array<Vector2i>,
+        goal_values: PackedFloat32Array,
+        blocked: Array<Vector2i>,
+        costs: Dictionary<Vector2i, f64>,
+        max_distance: i32,
+    ) -> Dictionary<Variant, Variant> {
+        let grid = PathGrid::new(&blocked, &costs, max_distance);
+        let goal_tiles: Vec<(i32, i32)> = goals.iter_shared().map(|v| (v.x, v.y)).collect();
+        let mut result = Dictionary::new();
+        if let Some((path, index, score)) =
+            grid.find_path_best_goal((from.x, from.y), &goal_tiles, goal_values.as_slice())
+        {
+            let (gx, gy) = goal_tiles[index];
+            result.set(
+                &Variant::from("path"),
+                &Variant::from(path_to_array(Some(path))),
+            );
+            result.set(
+                &Variant::from("goal"),
+                &Variant::from(Vector2i::new(gx, gy)),
+            );
+            result.set(&Variant::from("score"), &Variant::from(score));
+        }
+        result
+    }
+
+    /// One continuous path through `waypoints` in order: `find_path` between each
+    /// consecutive pair (with `max_distance` measured from the start of each leg),
+    /// joined without repeating the junction tiles. If any leg is unreachable, logs
+    /// an error naming it and returns an empty array.
+    #[func]
+    fn find_path_waypoints(
+        waypoints: Array<Vector2i>,
+        blocked: Array<Vector2i>,
+        costs: Dictionary<Vector2i, f64>,
+        max_distance: i32,
+    ) -> Array<Vector2i> {
+        let grid = PathGrid::new(&blocked, &costs, max_distance);
+        let points: Vec<(i32, i32)> = waypoints.iter_shared().map(|v| (v.x, v.y)).collect();
+        match grid.find_path_waypoints(&points) {
+            Ok(path) => path_to_array(Some(path)),
+            Err(i) => {
+                godot_error!(
+                    "find_path_waypoints: no path from waypoint {} {:?} to waypoint {} {:?}",
+                    i,
+                    points[i],
+                    i + 1,
+                    points[i + 1]
+                );
+                Array::new()
+            }
+        }
+    }
+
+    /// A* pathfinding around the units in `unit_index`: occupied tiles are impassable
+    /// when `treat_as_cost < 0`, otherwise entering one costs an extra `treat_as_cost`.
+    /// The moving unit's own tile `from` is never treated as occupied.
+    #[func]
+    fn find_path_avoiding_units(
+        from: Vector2i,
+        to: Vector2i,
+        unit_index: Gd<UnitIndex>,
+        treat_as_cost: f64,
+        max_distance: i32,
+    ) -> Array<Vector2i> {
+        let occupied = unit_index.bind().occupied_tiles();
+        path_to_array(path_avoiding(
+            (from.x, from.y),
+            (to.x, to.y),
+            occupied,
+            treat_as_cost,
+            max_distance,
+        ))
+    }
+
+    /// A* pathfinding that keeps at least `keep_away_radius + 1` hexes between the path
+    /// and every `danger_positions` tile (grid coordinates, rounded) whenever such a
+    /// route exists. If none does, the path may pass closer but enters as few
+    /// too-close tiles as possible. `from` itself is never penalized.
+    #[func]
+    fn find_path_safe_margin(
+        from: Vector2i,
+        to: Vector2i,
+        blocked: Array<Vector2i>,
+        costs: Dictionary<Vector2i, f64>,
+        max_distance: i32,
+        danger_positions: PackedVector2Array,
+        keep_away_radius: i32,
+    ) -> Array<Vector2i> {
+        let grid = PathGrid::new(&blocked, &costs, max_distance);
+        let mut margin: HashSet<(i32, i32)> = danger_positions
+            .as_slice()
+            .iter()
+            .flat_map(|p| hex_disk((p.x.round() as i32, p.y.round() as i32), keep_away_radius))
+            .collect();
+        margin.remove(&(from.x, from.y));
+        path_to_array(grid.find_path_margin((from.x, from.y), (to.x, to.y), &margin))
+    }
+
+    /// Path whose most dangerous tile is as safe as possible: minimizes the highest
+    /// `influence` (enemy influence, row-major; higher = more dangerous) among the
+    /// tiles entered after `from`, then the number of moves. Unlike additive danger
+    /// costs, any detour is worth it if it avoids the single worst tile.
+    /// Returns an empty array if `to` is unreachable.
+    #[func]
+    fn find_path_minimax_danger(
+        from: Vector2i,
+        to: Vector2i,
+        influence: PackedFloat32Array,
+        blocked: Array<Vector2i>,
+        map_width: i32,
+        map_height: i32,
+    ) -> Array<Vector2i> {
+        let blocked_mask = blocked_grid(&blocked, map_width, map_height);
+        path_to_array(minimax_danger_path(
+            (from.x, from.y),
+            (to.x, to.y),
+            influence.as_slice(),
+            &blocked_mask,
+            map_width,
+            map_height,
+        ))
+    }
+
+    /// Direction index (0-5, `hex_neighbors` order) from `from` to the adjacent tile `to`,
+    /// or -1 if the tiles are not adjacent.
+    #[func]
+    fn direction_between(from: Vector2i, to: Vector2i) -> i32 {
+        direction_between_xy((from.x, from.y), (to.x, to.y)).map_or(-1, |d| d as i32)
+    }
+
+    /// Neighbor of `from` in direction `dir` (0-5, `hex_neighbors` order), taking the
+    /// column parity into account. `dir` wraps modulo 6; the opposite direction is
+    /// `(dir + 3) % 6`.
+    #[func]
+    fn dir_to_offset(from: Vector2i, dir: i32) -> Vector2i {
+        let (x, y) = hex_neighbors_vec(from.x, from.y)[dir.rem_euclid(6) as usize];
+        Vector2i::new(x, y)
+    }
+
+    /// Alias of `dir_to_offset`.
+    #[func]
+    fn step(from: Vector2i, dir: i32) -> Vector2i {
+        Self::dir_to_offset(from, dir)
+    }
+
+    /// Bounce an out-of-bounds tile back onto the map by mirroring each violated
+    /// coordinate across the map edge, so one column past the edge lands on the edge
+    /// column. Tiles farther out than the map is wide (or tall) are clamped to the
+    /// far edge. In-bounds tiles are returned unchanged.
+    #[func]
+    fn reflect_into_bounds(pos: Vector2i, map_width: i32, map_height: i32) -> Vector2i {
+        Vector2i::new(
+            reflect_coord(pos.x, map_width),
+            reflect_coord(pos.y, map_height),
+        )
+    }
+
+    /// Whether `a` and `b` are hex neighbors, checked against `a`'s six neighbor
+    /// offsets directly. A tile is not adjacent to itself.
+    #[func]
+    fn are_adjacent(a: Vector2i, b: Vector2i) -> bool {
+        direction_between_xy((a.x, a.y), (b.x, b.y)).is_some()
+    }
+
+    /// A* pathfinding returning one direction index (0-5, `hex_neighbors` order) per
+    /// step instead of tiles. Empty if there is no path or `from == to`.
+    #[func]
+    fn find_path_directions(
+        from: Vector2i,
+        to: Vector2i,
+        blocked: Array<Vector2i>,
+        costs: Dictionary<Vector2i, f64>,
+        max_distance: i32,
+    ) -> PackedInt32Array {
+        let grid = PathGrid::new(&blocked, &costs, max_distance);
+        let path = grid
+            .find_path((from.x, from.y), (to.x, to.y))
+            .unwrap_or_default();
+        PackedInt32Array::from(path_directions(&path).as_slice())
+    }
+
+    /// A* pathfinding that prefers straight routes: each change of direction between
+    /// consecutive moves adds `turn_penalty` to the move cost.
+    /// With `turn_penalty = 0` this is identical to `find_path`.
+    #[func]
+    fn find_path_straight(
+        from: Vector2i,
+        to: Vector2i,
+        blocked: Array<Vector2i>,
+        costs: Dictionary<Vector2i, f64>,
+        max_distance: i32,
+        turn_penalty: f64,
+    ) -> Array<Vector2i> {
+        let grid = PathGrid::new(&blocked, &costs, max_distance);
+        path_to_array(grid.find_path_straight((from.x, from.y), (to.x, to.y), turn_penalty))
+    }
+
+    /// A* pathfinding that treats `soft_blocked` tiles (e.g. friendly units that can
+    /// move out of the way) as passable but adds `soft_cost` to entering them.
+    /// `blocked` tiles stay impassable.
+    #[func]
+    fn find_path_soft(
+        from: Vector2i,
+        to: Vector2i,
+        blocked: Array<Vector2i>,
+        costs: Dictionary<Vector2i, f64>,
+        max_distance: i32,
+        soft_blocked: Array<Vector2i>,
+        soft_cost: f64,
+    ) -> Array<Vector2i> {
+        let grid = PathGrid::new(&blocked, &costs, max_distance);
+        let soft: HashSet<(i32, i32)> = soft_blocked.iter_shared().map(|v| (v.x, v.y)).collect();
+        path_to_array(grid.find_path_soft((from.x, from.y), (to.x, to.y), &soft, soft_cost))
+    }
+
+    /// A* pathfinding to any tile adjacent to `target` (e.g. to attack an occupied city).
+    /// The path ends on that neighbor and never enters `target`, which may be blocked.
+    /// If `from` is already adjacent, returns `[from]`. Returns empty array if no path found.
+    #[func]
+    fn find_path_adjacent(
+        from: Vector2i,
+        target: Vector2i,
+        blocked: Array<Vector2i>,
+        costs: Dictionary<Vector2i, f64>,
+        max_distance: i32,
+    ) -> Array<Vector2i> {
+        let grid = PathGrid::new(&blocked, &costs, max_distance);
+        path_to_array(grid.find_path_adjacent((from.x, from.y), (target.x, target.y)))
+    }
+
+    /// A* pathfinding where climbing costs extra. Entering a tile costs
+    /// `cost + uphill_factor * max(0, height[to] - height[from])`, with `heights`
+    /// a row-major PackedInt32Array of size map_width*map_height.
+    /// Tiles outside the height map count as height 0.
+    /// With `uphill_factor = 0` this is identical to `find_path`.
+    #[func]
+    #[allow(clippy::too_many_arguments)]
+    fn find_path_elevation(
+        from: Vector2i,
+        to: Vector2i,
+        blocked: Array<Vector2i>,
+        costs: Dictionary<Vector2i, f64>,
+        max_distance: i32,
+        heights: PackedInt32Array,
+        uphill_factor: f64,
+        map_width: i32,
+        map_height: i32,
+    ) -> Array<Vector2i> {
+        let grid = PathGrid::new(&blocked, &costs, max_distance);
+        path_to_array(grid.find_path_elevation(
+            (from.x, from.y),
+            (to.x, to.y),
+            heights.as_slice(),
+            map_width,
+            map_height,
+            uphill_factor,
+        ))
+    }
+
+    /// A* pathfinding restricted by territory, e.g. for peacetime movement: tiles whose
+    /// owner in `owner_grid` (row-major, `map_width` wide; -1 = neutral, and tiles
+    /// outside the grid count as neutral) is not in `allowed_owners` are treated as
+    /// blocked. An empty `allowed_owners` disables the restriction.
+    #[func]
+    #[allow(clippy::too_many_arguments)]
+    fn find_path_owned(
+        from: Vector2i,
+        to: Vector2i,
+        blocked: Array<Vector2i>,
+        costs: Dictionary<Vector2i, f64>,
+        max_distance: i32,
+        owner_grid: PackedInt32Array,
+        allowed_owners: PackedInt32Array,
+        map_width: i32,
+    ) -> Array<Vector2i> {
+        let grid = PathGrid::new(&blocked, &costs, max_distance);
+        path_to_array(grid.find_path_owned(
+            (from.x, from.y),
+            (to.x, to.y),
+            owner_grid.as_slice(),
+            allowed_owners.as_slice(),
+            map_width,
+        ))
+    }
+
+    /// A* pathfinding with a per-direction multiplier, e.g. for winds or currents:
+    /// moving in direction `d` (`hex_neighbors` order) costs the tile's entry cost
+    /// times `directional_costs[d]` (missing entries count as 1.0). With all ones
+    /// this is identical to `find_path`.
+    #[func]
+    fn find_path_directional(
+        from: Vector2i,
+        to: Vector2i,
+        blocked: Array<Vector2i>,
+        costs: Dictionary<Vector2i, f64>,
+        max_distance: i32,
+        directional_costs: PackedFloat32Array,
+    ) -> Array<Vector2i> {
+        let grid = PathGrid::new(&blocked, &costs, max_distance);
+        path_to_array(grid.find_path_directional(
+            (from.x, from.y),
+            (to.x, to.y),
+            directional_costs.as_slice(),
+        ))
+    }
+}
+
+/// Convert odd-q offset to axial coordinates.
+fn to_axial(pos: Vector2i) -> (i32, i32) {
+    let x = pos.x;
+    let y = pos.y - (pos.x - (pos.x & 1)) / 2;
+    (x, y)
+}
+
+/// Convert axial to odd-q offset coordinates.
+fn from_axial(q: i32, r: i32) -> (i32, i32) {
+    (q, r + (q - (q & 1)) / 2)
+}
+
+/// Index (0-5, `hex_neighbors` order) of the direction from `from` to the adjacent `to`.
+fn direction_between_xy(from: (i32, i32), to: (i32, i32)) -> Option<usize> {
+    hex_neighbors_vec(from.0, from.1)
+        .iter()
+        .position(|&n| n == to)
+}
+
+/// Direction index of each step along a path of adjacent tiles.
+fn path_directions(path: &[(i32, i32)]) -> Vec<i32> {
+    path.windows(2)
+        .filter_map(|step| direction_between_xy(step[0], step[1]))
+        .map(|d| d as i32)
+        .collect()
+}
+
+/// Axial direction vectors, in the same order as `hex_neighbors`.
+const AXIAL_DIRS: [(i32, i32); 6] = [(1, 0), (1, -1), (0, -1), (-1, 0), (-1, 1), (0, 1)];
+
+/// Axial neighbors of an axial tile, in the same order as `hex_neighbors`.
+fn axial_neighbors(pos: (i32, i32)) -> [(i32, i32); 6] {
+    AXIAL_DIRS.map(|(dq, dr)| (pos.0 + dq, pos.1 + dr))
+}
+
+/// Hex distance between two axial tiles.
+fn axial_distance(a: (i32, i32), b: (i32, i32)) -> i32 {
+    let (dq, dr) = (a.0 - b.0, a.1 - b.1);
+    (dq.abs() + (dq + dr).abs() + dr.abs()) / 2
+}
+
+/// Ring tiles from corner `start_dir` to corner `end_dir` (inclusive), walking
+/// clockwise (decreasing direction order). Equal directions give the whole ring.
+fn ring_segment_vec(
+    center: (i32, i32),
+    radius: i32,
+    start_dir: i32,
+    end_dir: i32,
+) -> Vec<(i32, i32)> {
+    if radius <= 0 {
+        return vec![center];
+    }
+    let start_dir = start_dir.rem_euclid(6) as usize;
+    let edges = match (start_dir + 6 - end_dir.rem_euclid(6) as usize) % 6 {
+        0 => 6,
+        n => n,
+    };
+    let (cq, cr) = to_axial(Vector2i::new(center.0, center.1));
+    let (dq, dr) = AXIAL_DIRS[start_dir];
+    let (mut q, mut r) = (cq + dq * radius, cr + dr * radius);
+    let mut tiles = Vec::new();
+    for edge in 0..edges {
+        // Walking from corner d to corner d - 1 follows direction d - 2
+        let (sq, sr) = AXIAL_DIRS[(start_dir + 10 - edge) % 6];
+        for _ in 0..radius {
+            tiles.push(from_axial(q, r));
+            q += sq;
+            r += sr;
+        }
+    }
+    if edges < 6 {
+        tiles.push(from_axial(q, r));
+    }
+    tiles
+}
+
+/// Get hex neighbors for odd-q offset coordinates (standalone helper).
+fn hex_neighbors_vec(x: i32, y: i32) -> [(i32, i32); 6] {
+    if x & 1 == 0 {
+        [
+            (x + 1, y),
+            (x + 1, y - 1),
+            (x, y - 1),
+            (x - 1, y - 1),
+            (x - 1, y),
+            (x, y + 1),
+        ]
+    } else {
+        [
+            (x + 1, y + 1),
+            (x + 1, y),
+            (x, y - 1),
+            (x - 1, y),
+            (x - 1, y + 1),
+            (x, y + 1),
+        ]
+    }
+}
+
+/// Flat indices of the neighbors of (x, y) in `hex_neighbors_vec` order, -1 if out of bounds.
+fn neighbor_indices_vec(x: i32, y: i32, map_width: i32, map_height: i32) -> [i32; 6] {
+    hex_neighbors_vec(x, y).map(|n| grid_index(n, map_width, map_height).map_or(-1, |i| i as i32))
+}
+
+/// Row-major index of `pos` in a map_width x map_height grid, or `None` if out of bounds.
+fn grid_index(pos: (i32, i32), map_width: i32, map_height: i32) -> Option<usize> {
+    if pos.0 < 0 || pos.1 < 0 || pos.0 >= map_width || pos.1 >= map_height {
+        return None;
+    }
+    Some(pos.1 as usize * map_width as usize + pos.0 as usize)
+}
+
+/// Rows needed to hold `len` row-major cells at `map_width` per row (0 if the width
+/// is not positive), for grids passed without a height.
+fn grid_rows(len: usize, map_width: i32) -> i32 {
+    if map_width > 0 {
+        (len as i32 + map_width - 1) / map_width
+    } else {
+        0
+    }
+}
+
+/// Row-major mask of the in-bounds tiles listed in `blocked`.
+fn blocked_grid(blocked: &Array<Vector2i>, map_width: i32, map_height: i32) -> Vec<bool> {
+    let mut mask = vec![false; (map_width.max(0) * map_height.max(0)) as usize];
+    for v in blocked.iter_shared() {
+        if let Some(i) = grid_index((v.x, v.y), map_width, map_height) {
+            mask[i] = true;
+        }
+    }
+    mask
+}
+
+/// Multi-source BFS step distances over the map (-1 = unreachable).
+/// Only tiles for which `passable(index)` holds are entered; sources always count as reached.
+fn bfs_distance_grid(
+    sources: &[(i32, i32)],
+    map_width: i32,
+    map_height: i32,
+    passable: impl Fn(usize) -> bool,
+) -> Vec<i32> {
+    let mut dist = vec![-1; (map_width.max(0) * map_height.max(0)) as usize];
+    let mut queue = std::collections::VecDeque::new();
+    for &src in sources {
+        if let Some(i) = grid_index(src, map_width, map_height) {
+            if dist[i] < 0 {
+                dist[i] = 0;
+                queue.push_back(src);
+            }
+        }
+    }
+    while let Some((x, y)) = queue.pop_front() {
+        let d = dist[grid_index((x, y), map_width, map_height).unwrap()];
+        for n in hex_neighbors_vec(x, y) {
+            if let Some(ni) = grid_index(n, map_width, map_height) {
+                if dist[ni] < 0 && passable(ni) {
+                    dist[ni] = d + 1;
+                    queue.push_back(n);
+                }
+            }
+        }
+    }
+    dist
+}
+
+/// Double-BFS diameter estimate of the unblocked region around `start`; -1 if
+/// `start` is off the map or blocked.
+fn region_diameter_estimate(
+    start: (i32, i32),
+    blocked: &HashSet<(i32, i32)>,
+    map_width: i32,
+    map_height: i32,
+) -> i32 {
+    if grid_index(start, map_width, map_height).is_none() || blocked.contains(&start) {
+        return -1;
+    }
+    let open = |i: usize| !blocked.contains(&(i as i32 % map_width, i as i32 / map_width));
+    // Farthest reached tile, ties to the lowest index
+    let farthest = |dist: &[i32]| {
+        let (i, &d) = dist
+            .iter()
+            .enumerate()
+            .rev()
+            .max_by_key(|&(_, &d)| d)
+            .unwrap();
+        ((i as i32 % map_width, i as i32 / map_width), d)
+    };
+    let first = bfs_distance_grid(&[start], map_width, map_height, open);
+    let (a, _) = farthest(&first);
+    let second = bfs_distance_grid(&[a], map_width, map_height, open);
+    farthest(&second).1
+}
+
+/// Hex distance from every tile to the nearest on-map obstacle (-1 everywhere if none).
+/// Obstacles don't block each other, so plain BFS steps equal hex distance.
+fn obstacle_distances(obstacles: &[(i32, i32)], map_width: i32, map_height: i32) -> Vec<i32> {
+    bfs_distance_grid(obstacles, map_width, map_height, |_| true)
+}
+
+/// BFS out to `steps` moves from `from`, then walk back from the reachable tile with
+/// the highest influence. BFS order makes ties resolve to the fewest steps.
+fn retreat_path(
+    from: (i32, i32),
+    influence: &[f32],
+    blocked: &[bool],
+    steps: i32,
+    map_width: i32,
+    map_height: i32,
+) -> Option<Vec<(i32, i32)>> {
+    let start = grid_index(from, map_width, map_height)?;
+    let value = |i: usize| influence.get(i).copied().unwrap_or(f32::MIN);
+    let mut parent: HashMap<(i32, i32), (i32, i32)> = HashMap::new();
+    let mut frontier = vec![from];
+    let (mut best, mut best_value) = (from, value(start));
+    for _ in 0..steps.max(0) {
+        let mut next = Vec::new();
+        for &(x, y) in &frontier {
+            for n in hex_neighbors_vec(x, y) {
+                let Some(ni) = grid_index(n, map_width, map_height) else {
+                    continue;
+                };
+                if n == from || blocked.get(ni) == Some(&true) || parent.contains_key(&n) {
+                    continue;
+                }
+                parent.insert(n, (x, y));
+                if value(ni) > best_value {
+                    best = n;
+                    best_value = value(ni);
+                }
+                next.push(n);
+            }
+        }
+        frontier = next;
+    }
+    let mut path = vec![best];
+    while let Some(&prev) = parent.get(path.last().unwrap()) {
+        path.push(prev);
+    }
+    path.reverse();
+    Some(path)
+}
+
+/// Bottleneck search: binary search over the distinct danger values for the lowest
+/// ceiling that still connects `from` to `to`, then the shortest path under it.
+fn minimax_danger_path(
+    from: (i32, i32),
+    to: (i32, i32),
+    danger: &[f32],
+    blocked: &[bool],
+    map_width: i32,
+    map_height: i32,
+) -> Option<Vec<(i32, i32)>> {
+    grid_index(from, map_width, map_height)?;
+    let danger_at = |i: usize| danger.get(i).copied().unwrap_or(0.0);
+    let path_under = |ceiling: f32| {
+        astar(from, to, SearchLimits::default(), |_, next| {
+            let i = grid_index(next, map_width, map_height)?;
+            (blocked.get(i) != Some(&true) && danger_at(i) <= ceiling).then_some(1.0)
+        })
+    };
+    let mut ceilings: Vec<f32> = (0..(map_width * map_height) as usize)
+        .map(danger_at)
+        .collect();
+    ceilings.sort_unstable_by(f32::total_cmp);
+    ceilings.dedup();
+    // The highest ceiling only excludes blocked tiles; if that fails nothing will.
+    let mut best = path_under(*ceilings.last()?)?;
+    let (mut lo, mut hi) = (0, ceilings.len() - 1);
+    while lo < hi {
+        let mid = (lo + hi) / 2;
+        match path_under(ceilings[mid]) {
+            Some(path) => {
+                best = path;
+                hi = mid;
+            }
+            None => lo = mid + 1,
+        }
+    }
+    Some(best)
+}
+
+/// `HexMath.next_step` on plain collections; `None` means the path must be recomputed.
+fn path_next_step(
+    path: &[(i32, i32)],
+    current: (i32, i32),
+    blocked: &HashSet<(i32, i32)>,
+) -> Option<(i32, i32)> {
+    let i = path.iter().position(|&p| p == current)?;
+    match path.get(i + 1) {
+        None => Some(current),
+        Some(next) if blocked.contains(next) => None,
+        Some(&next) => Some(next),
+    }
+}
+
+/// `(from, to)` of a `[from, to]` Vector2i pair Variant.
+fn path_request(request: &Variant) -> Option<((i32, i32), (i32, i32))> {
+    let pair = request.try_to::<Array<Variant>>().ok()?;
+    let from = pair.get(0)?.try_to::<Vector2i>().ok()?;
+    let to = pair.get(1)?.try_to::<Vector2i>().ok()?;
+    Some(((from.x, from.y), (to.x, to.y)))
+}
+
+/// Mirror `v` into `0..size` across the edge it crossed (-1 -> 0, size -> size - 1).
+fn reflect_coord(v: i32, size: i32) -> i32 {
+    let max = size.max(1) - 1;
+    let mirrored = if v < 0 {
+        -1 - v
+    } else if v > max {
+        2 * max + 1 - v
+    } else {
+        v
+    };
+    mirrored.clamp(0, max)
+}
+
+fn bounding_rect(tiles: &[(i32, i32)]) -> Rect2i {
+    let Some(&(x0, y0)) = tiles.first() else {
+        return Rect2i::default();
+    };
+    let (mut min, mut max) = ((x0, y0), (x0, y0));
+    for &(x, y) in tiles {
+        min = (min.0.min(x), min.1.min(y));
+        max = (max.0.max(x), max.1.max(y));
+    }
+    Rect2i::new(
+        Vector2i::new(min.0, min.1),
+        Vector2i::new(max.0 - min.0 + 1, max.1 - min.1 + 1),
+    )
+}
+
+/// In-bounds neighbors of `region` not in `region`, in first-seen order.
+fn region_frontier(region: &[(i32, i32)], map_width: i32, map_height: i32) -> Vec<(i32, i32)> {
+    let inside: HashSet<(i32, i32)> = region.iter().copied().collect();
+    let mut seen = HashSet::new();
+    let mut frontier = Vec::new();
+    for &(x, y) in region {
+        for n in hex_neighbors_vec(x, y) {
+            if grid_index(n, map_width, map_height).is_some()
+                && !inside.contains(&n)
+                && seen.insert(n)
+            {
+                frontier.push(n);
+            }
+        }
+    }
+    frontier
+}
+
+fn hex_center(pos: Vector2i, layout_size: Vector2, origin: Vector2) -> Vector2 {
+    let (q, r) = to_axial(pos);
+    let (q, r) = (q as f64, r as f64);
+    let x = layout_size.x as f64 * 1.5 * q;
+    let y = layout_size.y as f64 * 3f64.sqrt() * (r + q / 2.0);
+    Vector2::new(origin.x + x as f32, origin.y + y as f32)
+}
+
+/// Offset tile under `pixel`: undo the layout scaling into fractional axial
+/// coordinates, then round in cube space.
+fn pixel_tile(pixel: Vector2, layout_size: Vector2, origin: Vector2) -> (i32, i32) {
+    let px = (pixel.x - origin.x) as f64 / layout_size.x as f64;
+    let py = (pixel.y - origin.y) as f64 / layout_size.y as f64;
+    let q = px * 2.0 / 3.0;
+    let r = py / 3f64.sqrt() - q / 2.0;
+    let (rq, rr, _) = cube_round(q, r, -q - r);
+    from_axial(rq, rr)
+}
+
+/// Convex hull of tile centers on the integer lattice `(3q, 2r + q)`, which is the
+/// pixel layout up to per-axis scaling, so turns and collinearity are exact.
+/// Andrew's monotone chain; returns lattice points clockwise on screen (y down).
+fn hull_lattice(tiles: &[(i32, i32)]) -> Vec<(i64, i64)> {
+    let mut points: Vec<(i64, i64)> = tiles
+        .iter()
+        .map(|&pos| {
+            let (q, r) = to_axial(Vector2i::new(pos.0, pos.1));
+            (3 * q as i64, 2 * r as i64 + q as i64)
+        })
+        .collect();
+    points.sort_unstable();
+    points.dedup();
+    if points.len() < 3 {
+        return points;
+    }
+    let cross = |o: (i64, i64), a: (i64, i64), b: (i64, i64)| {
+        (a.0 - o.0) * (b.1 - o.1) - (a.1 - o.1) * (b.0 - o.0)
+    };
+    let mut hull: Vec<(i64, i64)> = Vec::with_capacity(points.len() + 1);
+    for pass in [points.clone(), points.into_iter().rev().collect()] {
+        let base = hull.len();
+        for p in pass {
+            while hull.len() >= base + 2
+                && cross(hull[hull.len() - 2], hull[hull.len() - 1], p) <= 0
+            {
+                hull.pop();
+            }
+            hull.push(p);
+        }
+        hull.pop();
+    }
+    if hull.len() == 2 && hull[0] == hull[1] {
+        hull.truncate(1);
+    }
+    hull
+}
+
+/// Land tiles, sea tiles and boardable transport indices for
+/// `HexMath.amphibious_reachable`.
+#[allow(clippy::type_complexity)]
+fn amphibious_tiles(
+    from: (i32, i32),
+    transports: &[(i32, i32)],
+    land_budget: f64,
+    sea_budget: f64,
+    tile_types: &[i32],
+    map_width: i32,
+    map_height: i32,
+) -> (Vec<(i32, i32)>, Vec<(i32, i32)>, Vec<i32>) {
+    const WATER: i32 = 3;
+    let is_water = |pos: (i32, i32)| {
+        grid_index(pos, map_width, map_height).is_some_and(|i| tile_types.get(i) == Some(&WATER))
+    };
+    let mut land_grid = PathGrid {
+        blocked: impassable_tiles(tile_types, &[WATER], map_width, map_height),
+        costs: HashMap::new(),
+        max_distance: i32::MAX,
+    };
+    if land_grid.blocked.contains(&from) {
+        return (Vec::new(), Vec::new(), Vec::new());
+    }
+    let land = land_grid.reachable(from, land_budget, false);
+    let shore: HashSet<(i32, i32)> = land.iter().copied().collect();
+    let boardable: Vec<i32> = (0..transports.len())
+        .filter(|&t| {
+            let (x, y) = transports[t];
+            is_water((x, y)) && hex_neighbors_vec(x, y).iter().any(|n| shore.contains(n))
+        })
+        .map(|t| t as i32)
+        .collect();
+
+    // Reuse the grid for the sea leg: everything but water is blocked.
+    land_grid.blocked = (-1..=map_height)
+        .flat_map(|y| (-1..=map_width).map(move |x| (x, y)))
+        .filter(|&pos| !is_water(pos))
+        .collect();
+    let mut sea: Vec<(i32, i32)> = boardable
+        .iter()
+        .flat_map(|&t| land_grid.reachable(transports[t as usize], sea_budget, false))
+        .collect::<HashSet<_>>()
+        .into_iter()
+        .collect();
+    sea.sort_unstable_by_key(|&(x, y)| (y, x));
+    (land, sea, boardable)
+}
+
+/// `grid.reachable` minus every tile within one hex of an enemy.
+fn safe_destinations(
+    grid: &PathGrid,
+    from: (i32, i32),
+    enemies: &[(i32, i32)],
+    budget: f64,
+) -> Vec<(i32, i32)> {
+    let zone_of_control: HashSet<(i32, i32)> =
+        enemies.iter().flat_map(|&e| hex_disk(e, 1)).collect();
+    grid.reachable(from, budget, false)
+        .into_iter()
+        .filter(|pos| !zone_of_control.contains(pos))
+        .collect()
+}
+
+#[allow(clippy::too_many_arguments)]
+fn firing_tiles(
+    grid: &PathGrid,
+    from: (i32, i32),
+    target: (i32, i32),
+    attack_range: i32,
+    budget: f64,
+    tile_types: &[i32],
+    map_width: i32,
+    map_height: i32,
+) -> Vec<(i32, i32)> {
+    let target_v = Vector2i::new(target.0, target.1);
+    grid.reachable(from, budget, false)
+        .into_iter()
+        .filter(|&pos| {
+            grid_index(pos, map_width, map_height).is_some()
+                && hex_distance_xy(pos, target) <= attack_range
+                && line_of_sight(
+                    Vector2i::new(pos.0, pos.1),
+                    target_v,
+                    tile_types,
+                    map_width,
+                    map_height,
+                )
+        })
+        .collect()
+}
+
+/// The tile in `candidates` nearest to `target` by hex distance, ties to the earliest.
+fn closest_tile(target: (i32, i32), candidates: &[(i32, i32)]) -> Option<(i32, i32)> {
+    candidates
+        .iter()
+        .copied()
+        .min_by_key(|&c| hex_distance_xy(target, c))
+}
+
+/// (tile, damage) for every in-bounds tile of the blast, in `hex_disk` order.
+fn blast_damage_tiles(
+    center: (i32, i32),
+    base_damage: i32,
+    radius: i32,
+    falloff_per_ring: i32,
+    map_width: i32,
+    map_height: i32,
+) -> Vec<((i32, i32), i32)> {
+    hex_disk(center, radius)
+        .into_iter()
+        .filter(|&pos| grid_index(pos, map_width, map_height).is_some())
+        .map(|pos| {
+            let ring = hex_distance_xy(center, pos);
+            let damage = base_damage.saturating_sub(falloff_per_ring.saturating_mul(ring));
+            (pos, damage.max(0))
+        })
+        .collect()
+}
+
+/// Offset tiles within hex `radius` of `center` (empty for a negative radius),
+/// enumerated in axial coordinates.
+fn hex_disk(center: (i32, i32), radius: i32) -> Vec<(i32, i32)> {
+    let (cq, cr) = to_axial(Vector2i::new(center.0, center.1));
+    let mut tiles = Vec::new();
+    for dq in -radius..=radius {
+        for dr in (-radius).max(-dq - radius)..=radius.min(-dq + radius) {
+            tiles.push(from_axial(cq + dq, cr + dr));
+        }
+    }
+    tiles
+}
+
+/// Spiral index of an axial tile relative to the origin; see `HexMath.spiral_index`.
+fn spiral_index_axial(pos: (i32, i32)) -> i32 {
+    let k = axial_distance(pos, (0, 0));
+    if k == 0 {
+        return 0;
+    }
+    let base = 1 + 3 * k * (k - 1);
+    for (d, &(cq, cr)) in AXIAL_DIRS.iter().enumerate() {
+        // Side d runs from corner d towards corner d + 1 in k steps
+        let (sq, sr) = AXIAL_DIRS[(d + 2) % 6];
+        let (oq, or) = (pos.0 - k * cq, pos.1 - k * cr);
+        let j = if sq != 0 { oq / sq } else { or / sr };
+        if (0..k).contains(&j) && (oq, or) == (j * sq, j * sr) {
+            return base + d as i32 * k + j;
+        }
+    }
+    unreachable!("every tile at distance k lies on one side of ring k")
+}
+
+/// Axial tile at `index` in the spiral around the origin.
+fn spiral_axial(index: i32) -> (i32, i32) {
+    if index <= 0 {
+        return (0, 0);
+    }
+    // Ring k ends just before index 1 + 3k(k + 1)
+    let mut k = 1;
+    while 3 * (k + 1) * k < index {
+        k += 1;
+    }
+    let offset = index - (1 + 3 * k * (k - 1));
+    let (d, j) = ((offset / k) as usize, offset % k);
+    let (cq, cr) = AXIAL_DIRS[d];
+    let (sq, sr) = AXIAL_DIRS[(d + 2) % 6];
+    (k * cq + j * sq, k * cr + j * sr)
+}
+
+fn path_avoiding(
+    from: (i32, i32),
+    to: (i32, i32),
+    mut occupied: HashSet<(i32, i32)>,
+    treat_as_cost: f64,
+    max_distance: i32,
+) -> Option<Vec<(i32, i32)>> {
+    occupied.remove(&from);
+    let mut grid = PathGrid {
+        blocked: HashSet::new(),
+        costs: HashMap::new(),
+        max_distance,
+    };
+    if treat_as_cost < 0.0 {
+        grid.blocked = occupied;
+        grid.find_path(from, to)
+    } else {
+        grid.find_path_soft(from, to, &occupied, treat_as_cost)
+    }
+}
+
+/// Hex distance between two odd-q offset positions given as tuples.
+fn hex_distance_xy(a: (i32, i32), b: (i32, i32)) -> i32 {
+    HexMath::hex_distance(Vector2i::new(a.0, a.1), Vector2i::new(b.0, b.1))
+}
+
+fn path_to_array(path: Option<Vec<(i32, i32)>>) -> Array<Vector2i> {
+    let mut result = Array::new();
+    for (x, y) in path.unwrap_or_default() {
+        result.push(Vector2i::new(x, y));
+    }
+    result
+}
+
+// ============================================================
+// Pathfinding core
+// ============================================================
+
+/// Open-set entry for A*. Ordered so that `BinaryHeap` pops the lowest `f` first.
+struct PathNode<S> {
+    pos: S,
+    g: f64,
+    f: f64,
+    steps: usize,
+}
+
+impl<S> PartialEq for PathNode<S> {
+    fn eq(&self, other: &Self) -> bool {
+        self.f == other.f
+    }
+}
+impl<S> Eq for PathNode<S> {}
+impl<S> PartialOrd for PathNode<S> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl<S> Ord for PathNode<S> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.f.partial_cmp(&self.f).unwrap_or(Ordering::Equal)
+    }
+}
+
+/// Bounds applied by `astar` on top of the step-cost closure.
+#[derive(Clone, Copy)]
+struct SearchLimits {
+    /// Maximum number of moves in a path.
+    max_steps: usize,
+    /// Maximum accumulated movement cost of a path.
+    max_cost: f64,
+    /// Maximum number of tiles expanded before the search gives up.
+    max_expansions: usize,
+}
+
+impl Default for SearchLimits {
+    fn default() -> Self {
+        Self {
+            max_steps: usize::MAX,
+            max_cost: f64::INFINITY,
+            max_expansions: usize::MAX,
+        }
+    }
+}
+
+/// A* over the hex grid with a hex-distance heuristic.
+/// `step_cost(from, to)` returns the cost of moving onto the neighbor `to`,
+/// or `None` if that move is not allowed.
+/// Step limits apply to the cheapest known route to each tile, so a costlier
+/// but shorter detour is not considered once a cheaper one has been found.
+fn astar(
+    start: (i32, i32),
+    goal: (i32, i32),
+    limits: SearchLimits,
+    step_cost: impl FnMut((i32, i32), (i32, i32)) -> Option<f64>,
+) -> Option<Vec<(i32, i32)>> {
+    astar_search(
+        start,
+        |pos| pos == goal,
+        |pos| hex_distance_xy(pos, goal) as f64,
+        limits,
+        None,
+        step_cost,
+    )
+}
+
+/// General form of `astar`: the search ends on the first tile accepted by `is_goal`,
+/// guided by `heuristic`, which must not overestimate the remaining cost.
+fn astar_search(
+    start: (i32, i32),
+    is_goal: impl Fn((i32, i32)) -> bool,
+    heuristic: impl Fn((i32, i32)) -> f64,
+    limits: SearchLimits,
+    trace: Option<&mut SearchTrace<(i32, i32)>>,
+    mut step_cost: impl FnMut((i32, i32), (i32, i32)) -> Option<f64>,
+) -> Option<Vec<(i32, i32)>> {
+    astar_states(start, is_goal, heuristic, limits, trace, |pos, out| {
+        for np in hex_neighbors_vec(pos.0, pos.1) {
+            if let Some(cost) = step_cost(pos, np) {
+                out.push((np, cost));
+            }
+        }
+    })
+}
+
+/// What an A* run explored, for debugging and visualization.
+struct SearchTrace<S> {
+    /// States in the order they were taken off the open set (stale entries skipped).
+    expanded: Vec<S>,
+    /// Best known cost to every state reached.
+    g_scores: HashMap<S, f64>,
+}
+
+impl<S> Default for SearchTrace<S> {
+    fn default() -> Self {
+        Self {
+            expanded: Vec::new(),
+            g_scores: HashMap::new(),
+        }
+    }
+}
+
+/// A* over arbitrary search states, for searches whose cost depends on more than
+/// the current tile (e.g. the direction of travel). `successors(state, out)` pushes
+/// `(next_state, cost)` pairs onto `out`. Returns the sequence of visited states.
+/// If `trace` is given it is filled with the explored states and their costs.
+fn astar_states<S: Copy + Eq + Hash>(
+    start: S,
+    is_goal: impl Fn(S) -> bool,
+    heuristic: impl Fn(S) -> f64,
+    limits: SearchLimits,
+    mut trace: Option<&mut SearchTrace<S>>,
+    mut successors: impl FnMut(S, &mut Vec<(S, f64)>),
+) -> Option<Vec<S>> {
+    let mut open = BinaryHeap::new();
+    let mut came_from: HashMap<S, S> = HashMap::new();
+    let mut g_scores: HashMap<S, f64> = HashMap::new();
+    let mut next = Vec::with_capacity(6);
+    let mut expansions = 0;
+
+    g_scores.insert(start, 0.0);
+    open.push(PathNode {
+        pos: start,
+        g: 0.0,
+        f: heuristic(start),
+        steps: 0,
+    });
+
+    while let Some(current) = open.pop() {
+        if let Some(trace) = trace.as_deref_mut() {
+            if current.g <= *g_scores.get(&current.pos).unwrap_or(&f64::MAX) {
+                trace.expanded.push(current.pos);
+            }
+        }
+        if is_goal(current.pos) {
+            // Reconstruct path
+            let mut path = vec![current.pos];
+            let mut cur = current.pos;
+            while cur != start {
+                cur = came_from[&cur];
+                path.push(cur);
+            }
+            path.reverse();
+            if let Some(trace) = trace {
+                trace.g_scores = g_scores;
+            }
+            return Some(path);
+        }
+
+        let current_g = *g_scores.get(&current.pos).unwrap_or(&f64::MAX);
+        if current.g > current_g || current.steps >= limits.max_steps {
+            continue;
+        }
+        if expansions >= limits.max_expansions {
+            break;
+        }
+        expansions += 1;
+
+        next.clear();
+        successors(current.pos, &mut next);
+        for &(np, cost) in &next {
+            let tentative_g = current_g + cost;
+            if tentative_g > limits.max_cost {
+                continue;
+            }
+            let prev_g = *g_scores.get(&np).unwrap_or(&f64::MAX);
+            if tentative_g < prev_g {
+                came_from.insert(np, current.pos);
+                g_scores.insert(np, tentative_g);
+                open.push(PathNode {
+                    pos: np,
+                    g: tentative_g,
+                    f: tentative_g + heuristic(np),
+                    steps: current.steps + 1,
+                });
+            }
+        }
+    }
+
+    if let Some(trace) = trace {
+        trace.g_scores = g_scores;
+    }
+    None // No path found
+}
+
+/// Blocked tiles, movement costs and search radius shared by the `find_path*` functions.
+struct PathGrid {
+    blocked: HashSet<(i32, i32)>,
+    costs: HashMap<(i32, i32), f64>,
+    max_distance: i32,
+}
+
+impl PathGrid {
+    fn new(
+        blocked: &Array<Vector2i>,
+        costs: &Dictionary<Vector2i, f64>,
+        max_distance: i32,
+    ) -> Self {
+        Self {
+            blocked: blocked.iter_shared().map(|v| (v.x, v.y)).collect(),
+            costs: costs
+                .iter_shared()
+                .map(|(pos, cost)| ((pos.x, pos.y), cost))
+                .collect(),
+            max_distance,
+        }
+    }
+
+    /// Movement cost of entering `pos` (default 1.0).
+    fn cost(&self, pos: (i32, i32)) -> f64 {
+        self.costs.get(&pos).copied().unwrap_or(1.0)
+    }
+
+    /// Whether a search starting at `start` may enter `pos`.
+    fn is_open(&self, start: (i32, i32), pos: (i32, i32)) -> bool {
+        !self.blocked.contains(&pos) && hex_distance_xy(start, pos) <= self.max_distance
+    }
+
+    fn find_path(&self, start: (i32, i32), goal: (i32, i32)) -> Option<Vec<(i32, i32)>> {
+        self.find_path_limited(start, goal, SearchLimits::default())
+    }
+
+    fn find_path_limited(
+        &self,
+        start: (i32, i32),
+        goal: (i32, i32),
+        limits: SearchLimits,
+    ) -> Option<Vec<(i32, i32)>> {
+        self.find_path_traced(start, goal, limits, None)
+    }
+
+    /// `find_path` for each `(start, goal)` request in order; `None` requests
+    /// (malformed input) give no path.
+    #[allow(clippy::type_complexity)]
+    fn find_paths(
+        &self,
+        requests: &[Option<((i32, i32), (i32, i32))>],
+    ) -> Vec<Option<Vec<(i32, i32)>>> {
+        requests
+            .iter()
+            .map(|request| request.and_then(|(start, goal)| self.find_path(start, goal)))
+            .collect()
+    }
+
+    fn find_path_ordered(
+        &self,
+        start: (i32, i32),
+        goal: (i32, i32),
+        reverse: bool,
+    ) -> Option<Vec<(i32, i32)>> {
+        let mut path = self.find_path(start, goal)?;
+        if reverse {
+            path.reverse();
+        }
+        Some(path)
+    }
+
+    fn is_path_valid(
+        &self,
+        path: &[(i32, i32)],
+        budget: f64,
+        map_width: i32,
+        map_height: i32,
+    ) -> bool {
+        if path.is_empty() {
+            return false;
+        }
+        let tiles_ok = path.iter().all(|&pos| {
+            grid_index(pos, map_width, map_height).is_some() && !self.blocked.contains(&pos)
+        });
+        let steps_ok = path
+            .windows(2)
+            .all(|step| direction_between_xy(step[0], step[1]).is_some());
+        let cost: f64 = path[1..].iter().map(|&pos| self.cost(pos)).sum();
+        tiles_ok && steps_ok && cost <= budget
+    }
+
+    /// `find_path_limited` that also records the search in `trace`.
+    fn find_path_traced(
+        &self,
+        start: (i32, i32),
+        goal: (i32, i32),
+        limits: SearchLimits,
+        trace: Option<&mut SearchTrace<(i32, i32)>>,
+    ) -> Option<Vec<(i32, i32)>> {
+        if self.blocked.contains(&goal) {
+            return None;
+        }
+        astar_search(
+            start,
+            |pos| pos == goal,
+            |pos| hex_distance_xy(pos, goal) as f64,
+            limits,
+            trace,
+            |_, to| self.is_open(start, to).then(|| self.cost(to)),
+        )
+    }
+
+    /// Up to `k` cheapest loopless paths for `HexMath.find_k_paths` (Yen's algorithm).
+    /// Each new path branches off the previous one at a spur tile: the root before it
+    /// is kept, the root's tiles are closed, and so are the steps out of the spur
+    /// tile taken by accepted paths sharing that root.
+    fn find_k_shortest_paths(
+        &self,
+        start: (i32, i32),
+        goal: (i32, i32),
+        k: usize,
+    ) -> Vec<Vec<(i32, i32)>> {
+        let Some(first) = self.find_path(start, goal).filter(|_| k > 0) else {
+            return Vec::new();
+        };
+        let path_cost = |path: &[(i32, i32)]| path[1..].iter().map(|&p| self.cost(p)).sum::<f64>();
+        let mut paths = vec![first];
+        let mut candidates: Vec<(f64, Vec<(i32, i32)>)> = Vec::new();
+        while paths.len() < k {
+            let last = paths.last().unwrap().clone();
+            for i in 0..last.len() - 1 {
+                let (root, spur) = (&last[..i], last[i]);
+                let taken: HashSet<(i32, i32)> = paths
+                    .iter()
+                    .filter(|p| p.len() > i + 1 && p[..=i] == last[..=i])
+                    .map(|p| p[i + 1])
+                    .collect();
+                let spur_path = astar(spur, goal, SearchLimits::default(), |from, to| {
+                    let open = self.is_open(start, to)
+                        && !root.contains(&to)
+                        && !(from == spur && taken.contains(&to));
+                    open.then(|| self.cost(to))
+                });
+                let Some(spur_path) = spur_path else {
+                    continue;
+                };
+                let mut path = root.to_vec();
+                path.extend(spur_path);
+                if !candidates.iter().any(|(_, p)| *p == path) {
+                    candidates.push((path_cost(&path), path));
+                }
+            }
+            // Cheapest candidate, earliest found on ties
+            let Some(best) = candidates
+                .iter()
+                .enumerate()
+                .min_by(|a, b| a.1 .0.total_cmp(&b.1 .0))
+                .map(|(i, _)| i)
+            else {
+                break;
+            };
+            paths.push(candidates.remove(best).1);
+        }
+        paths
+    }
+
+    /// `find_path` with entry costs from `cost` instead of `self.costs`, evaluated
+    /// lazily and cached so each tile is asked for at most once.
+    fn find_path_with(
+        &self,
+        start: (i32, i32),
+        goal: (i32, i32),
+        mut cost: impl FnMut((i32, i32)) -> f64,
+    ) -> Option<Vec<(i32, i32)>> {
+        if self.blocked.contains(&goal) {
+            return None;
+        }
+        let mut cache: HashMap<(i32, i32), f64> = HashMap::new();
+        astar(start, goal, SearchLimits::default(), |_, to| {
+            self.is_open(start, to)
+                .then(|| *cache.entry(to).or_insert_with(|| cost(to)))
+        })
+    }
+
+    /// A* where entering a tile in `soft_blocked` costs an extra `soft_cost`.
+    fn find_path_soft(
+        &self,
+        start: (i32, i32),
+        goal: (i32, i32),
+        soft_blocked: &HashSet<(i32, i32)>,
+        soft_cost: f64,
+    ) -> Option<Vec<(i32, i32)>> {
+        if self.blocked.contains(&goal) {
+            return None;
+        }
+        astar(start, goal, SearchLimits::default(), |_, to| {
+            if !self.is_open(start, to) {
+                return None;
+            }
+            let extra = if soft_blocked.contains(&to) {
+                soft_cost
+            } else {
+                0.0
+            };
+            Some(self.cost(to) + extra)
+        })
+    }
+
+    /// A* where each change of direction between consecutive moves adds `turn_penalty`.
+    fn find_path_straight(
+        &self,
+        start: (i32, i32),
+        goal: (i32, i32),
+        turn_penalty: f64,
+    ) -> Option<Vec<(i32, i32)>> {
+        if turn_penalty == 0.0 {
+            return self.find_path(start, goal);
+        }
+        if self.blocked.contains(&goal) {
+            return None;
+        }
+        // State is (tile, direction of the move that entered it); 6 = no move yet.
+        let path = astar_states(
+            (start, 6),
+            |(pos, _)| pos == goal,
+            |(pos, _)| hex_distance_xy(pos, goal) as f64,
+            SearchLimits::default(),
+            None,
+            |(pos, dir), out| {
+                for (d, np) in hex_neighbors_vec(pos.0, pos.1).into_iter().enumerate() {
+                    if !self.is_open(start, np) {
+                        continue;
+                    }
+                    let turn = if dir != 6 && dir != d {
+                        turn_penalty
+                    } else {
+                        0.0
+                    };
+                    out.push(((np, d), self.cost(np) + turn));
+                }
+            },
+        )?;
+        Some(path.into_iter().map(|(pos, _)| pos).collect())
+    }
+
+    /// `find_path` through each waypoint in turn, joined without repeating the junction
+    /// tiles. `Err(i)` if the leg from `waypoints[i]` to `waypoints[i + 1]` has no path.
+    fn find_path_waypoints(&self, waypoints: &[(i32, i32)]) -> Result<Vec<(i32, i32)>, usize> {
+        let mut path: Vec<(i32, i32)> = waypoints.first().copied().into_iter().collect();
+        for (i, leg) in waypoints.windows(2).enumerate() {
+            let segment = self.find_path(leg[0], leg[1]).ok_or(i)?;
+            path.extend_from_slice(&segment[1..]);
+        }
+        Ok(path)
+    }
+
+    /// Dijkstra from `start` over open tiles: cheapest cost to every tile reachable
+    /// within `max_cost`, together with the tile it was reached from (`start` maps
+    /// to itself). With `axial` set, tiles, `blocked` and `costs` are axial.
+    fn cost_tree(
+        &self,
+        start: (i32, i32),
+        max_cost: f64,
+        axial: bool,
+    ) -> HashMap<(i32, i32), (f64, (i32, i32))> {
+        let mut best: HashMap<(i32, i32), (f64, (i32, i32))> = HashMap::new();
+        let mut open = BinaryHeap::new();
+        best.insert(start, (0.0, start));
+        open.push(PathNode {
+            pos: start,
+            g: 0.0,
+            f: 0.0,
+            steps: 0,
+        });
+        while let Some(current) = open.pop() {
+            if current.g > best[&current.pos].0 {
+                continue;
+            }
+            let neighbors = if axial {
+                axial_neighbors(current.pos)
+            } else {
+                hex_neighbors_vec(current.pos.0, current.pos.1)
+            };
+            for np in neighbors {
+                let passable = if axial {
+                    !self.blocked.contains(&np) && axial_distance(start, np) <= self.max_distance
+                } else {
+                    self.is_open(start, np)
+                };
+                if !passable {
+                    continue;
+                }
+                let g = current.g + self.cost(np);
+                if g > max_cost {
+                    continue;
+                }
+                if best.get(&np).is_none_or(|&(prev, _)| g < prev) {
+                    best.insert(np, (g, current.pos));
+                    open.push(PathNode {
+                        pos: np,
+                        g,
+                        f: g,
+                        steps: current.steps + 1,
+                    });
+                }
+            }
+        }
+        best
+    }
+
+    /// Tiles reachable from `start` for at most `budget` total cost, cheapest first
+    /// (ties in row-major order), `start` included.
+    fn reachable(&self, start: (i32, i32), budget: f64, axial: bool) -> Vec<(i32, i32)> {
+        let mut tiles: Vec<((i32, i32), f64)> = self
+            .cost_tree(start, budget, axial)
+            .into_iter()
+            .map(|(pos, (g, _))| (pos, g))
+            .collect();
+        tiles.sort_by(|a, b| {
+            a.1.total_cmp(&b.1)
+                .then((a.0 .1, a.0 .0).cmp(&(b.0 .1, b.0 .0)))
+        });
+        tiles.into_iter().map(|(pos, _)| pos).collect()
+    }
+
+    /// `find_path` over axial coordinates: `start`, `goal`, `blocked`, `costs` and the
+    /// returned path are all axial.
+    fn find_path_axial(&self, start: (i32, i32), goal: (i32, i32)) -> Option<Vec<(i32, i32)>> {
+        if self.blocked.contains(&goal) {
+            return None;
+        }
+        astar_states(
+            start,
+            |pos| pos == goal,
+            |pos| axial_distance(pos, goal) as f64,
+            SearchLimits::default(),
+            None,
+            |pos, out| {
+                for np in axial_neighbors(pos) {
+                    if !self.blocked.contains(&np) && axial_distance(start, np) <= self.max_distance
+                    {
+                        out.push((np, self.cost(np)));
+                    }
+                }
+            },
+        )
+    }
+
+    /// Among `goals` with their `values`, the reachable goal maximizing
+    /// `value - travel_cost` (earlier goals win ties), as (path, goal index, score).
+    #[allow(clippy::type_complexity)]
+    fn find_path_best_goal(
+        &self,
+        start: (i32, i32),
+        goals: &[(i32, i32)],
+        values: &[f32],
+    ) -> Option<(Vec<(i32, i32)>, usize, f64)> {
+        let tree = self.cost_tree(start, f64::INFINITY, false);
+        let mut best: Option<(usize, f64)> = None;
+        for (i, (goal, &value)) in goals.iter().zip(values).enumerate() {
+            let Some(&(cost, _)) = tree.get(goal) else {
+                continue;
+            };
+            let score = value as f64 - cost;
+            if best.is_none_or(|(_, s)| score > s) {
+                best = Some((i, score));
+            }
+        }
+        let (index, score) = best?;
+        let mut path = vec![goals[index]];
+        while *path.last().unwrap() != start {
+            path.push(tree[path.last().unwrap()].1);
+        }
+        path.reverse();
+        Some((path, index, score))
+    }
+
+    /// Tiles of a minimum spanning tree (Prim's) over the pairwise cheapest paths
+    /// between `cities`, grown from the first city, without duplicates. Cities on
+    /// blocked tiles are skipped.
+    fn road_network(&self, cities: &[(i32, i32)]) -> Vec<(i32, i32)> {
+        let cities: Vec<(i32, i32)> = cities
+            .iter()
+            .copied()
+            .filter(|c| !self.blocked.contains(c))
+            .collect();
+        let Some(&first) = cities.first() else {
+            return Vec::new();
+        };
+        let trees: Vec<_> = cities
+            .iter()
+            .map(|&c| self.cost_tree(c, f64::INFINITY, false))
+            .collect();
+        let mut connected = vec![false; cities.len()];
+        connected[0] = true;
+        let mut seen: HashSet<(i32, i32)> = HashSet::from([first]);
+        let mut tiles = vec![first];
+        loop {
+            // Cheapest link from the network to a city not yet on it
+            let mut best: Option<(f64, usize, usize)> = None;
+            for (i, tree) in trees.iter().enumerate().filter(|&(i, _)| connected[i]) {
+                for (j, city) in cities.iter().enumerate().filter(|&(j, _)| !connected[j]) {
+                    if let Some(&(cost, _)) = tree.get(city) {
+                        if best.is_none_or(|(c, _, _)| cost < c) {
+                            best = Some((cost, i, j));
+                        }
+                    }
+                }
+            }
+            let Some((_, i, j)) = best else {
+                break;
+            };
+            connected[j] = true;
+            let mut pos = cities[j];
+            let mut path = vec![pos];
+            while pos != cities[i] {
+                pos = trees[i][&pos].1;
+                path.push(pos);
+            }
+            for pos in path.into_iter().rev() {
+                if seen.insert(pos) {
+                    tiles.push(pos);
+                }
+            }
+        }
+        tiles
+    }
+
+    /// Path that stays out of `margin` (tiles too close to danger) when possible.
+    /// Without such a route, margin tiles are allowed at a prohibitive extra cost, so
+    /// the path enters as few of them as it can.
+    fn find_path_margin(
+        &self,
+        start: (i32, i32),
+        goal: (i32, i32),
+        margin: &HashSet<(i32, i32)>,
+    ) -> Option<Vec<(i32, i32)>> {
+        let strict = astar(start, goal, SearchLimits::default(), |_, to| {
+            (self.is_open(start, to) && !margin.contains(&to)).then(|| self.cost(to))
+        });
+        if strict.is_some() || self.blocked.contains(&goal) {
+            return strict;
+        }
+        self.find_path_soft(start, goal, margin, 1e6)
+    }
+
+    /// Path to the nearest-by-cost tile adjacent to `target`; `target` itself is never entered.
+    fn find_path_adjacent(&self, start: (i32, i32), target: (i32, i32)) -> Option<Vec<(i32, i32)>> {
+        astar_search(
+            start,
+            |pos| hex_distance_xy(pos, target) == 1,
+            |pos| (hex_distance_xy(pos, target) - 1).max(0) as f64,
+            SearchLimits::default(),
+            None,
+            |_, to| (to != target && self.is_open(start, to)).then(|| self.cost(to)),
+        )
+    }
+
+    fn find_path_elevation(
+        &self,
+        start: (i32, i32),
+        goal: (i32, i32),
+        heights: &[i32],
+        map_width: i32,
+        map_height: i32,
+        uphill_factor: f64,
+    ) -> Option<Vec<(i32, i32)>> {
+        if self.blocked.contains(&goal) {
+            return None;
+        }
+        let height_at = |pos| {
+            grid_index(pos, map_width, map_height)
+                .and_then(|i| heights.get(i).copied())
+                .unwrap_or(0)
+        };
+        astar(start, goal, SearchLimits::default(), |from, to| {
+            if !self.is_open(start, to) {
+                return None;
+            }
+            let climb = (height_at(to) - height_at(from)).max(0);
+            Some(self.cost(to) + uphill_factor * climb as f64)
+        })
+    }
+
+    /// A* that may only enter tiles whose owner is in `allowed` (all if empty).
+    fn find_path_owned(
+        &self,
+        start: (i32, i32),
+        goal: (i32, i32),
+        owner_grid: &[i32],
+        allowed: &[i32],
+        map_width: i32,
+    ) -> Option<Vec<(i32, i32)>> {
+        let rows = grid_rows(owner_grid.len(), map_width);
+        let owner_at = |pos| {
+            grid_index(pos, map_width, rows)
+                .and_then(|i| owner_grid.get(i).copied())
+                .unwrap_or(-1)
+        };
+        let may_enter = |pos| allowed.is_empty() || allowed.contains(&owner_at(pos));
+        if self.blocked.contains(&goal) || !may_enter(goal) {
+            return None;
+        }
+        astar(start, goal, SearchLimits::default(), |_, to| {
+            (self.is_open(start, to) && may_enter(to)).then(|| self.cost(to))
+        })
+    }
+
+    /// A* where each move's entry cost is scaled by `multipliers[direction]`.
+    fn find_path_directional(
+        &self,
+        start: (i32, i32),
+        goal: (i32, i32),
+        multipliers: &[f32],
+    ) -> Option<Vec<(i32, i32)>> {
+        if self.blocked.contains(&goal) {
+            return None;
+        }
+        let factor = |d: usize| multipliers.get(d).map_or(1.0, |&m| m as f64);
+        // Scale the heuristic down so cheap directions cannot make it overestimate.
+        let min_factor = (0..6).map(factor).fold(1.0, f64::min).max(0.0);
+        astar_search(
+            start,
+            |pos| pos == goal,
+            |pos| hex_distance_xy(pos, goal) as f64 * min_factor,
+            SearchLimits::default(),
+            None,
+            |from, to| {
+                let d = direction_between_xy(from, to)?;
+                self.is_open(start, to).then(|| self.cost(to) * factor(d))
+            },
+        )
+    }
+}
+
+// ============================================================
+// 1. InfluenceMap
+// ============================================================
+
+#[derive(GodotClass)]
+#[class(base=RefCounted, init)]
+pub struct InfluenceMap {
+    #[allow(dead_code)]
+    influence: Vec<Vec<f32>>, // per-player influence grids
+    width: usize,
+    height: usize,
+    num_players: usize,
+    history: VecDeque<Vec<Vec<f32>>>, // past net grids, oldest first
+    #[init(val = 8)]
+    history_length: usize,
+    use_hex_distance: bool,
+    passability: Vec<u8>,     // empty = everything passable
+    falloff: Vec<(f32, f32)>, // (sigma, weight) Gaussians per source; empty = single default
+    raw: Vec<Vec<f32>>,       // per-player influence before netting, kept for partial updates
+}
+
+#[godot_api]
+impl InfluenceMap {
+    /// Options for `compute`. With `use_hex_distance`, influence falls off with true
+    /// hex distance and is circular on the grid; otherwise (the default) it uses
+    /// `dx*dx + dy*dy` in offset coordinates, as before.
+    #[func]
+    fn configure(&mut self, use_hex_distance: bool) {
+        self.use_hex_distance = use_hex_distance;
+    }
+
+    /// Make `compute` stamp two Gaussians per source instead of one: a sharp local
+    /// component (`sigma_near`, typically small with a high weight) and a diffuse
+    /// strategic one (`sigma_far`, large with a low weight). Each is cut off at
+    /// 3 sigma and scaled by its weight; the net computation is unchanged.
+    /// The default is a single Gaussian with sigma 4 and weight 1; a `weight_far` of
+    /// 0, a sigma that is not positive and finite, or a non-finite weight restores it.
+    #[func]
+    fn configure_dual(
+        &mut self,
+        sigma_near: f32,
+        weight_near: f32,
+        sigma_far: f32,
+        weight_far: f32,
+    ) {
+        let valid_sigma = |sigma: f32| sigma.is_finite() && sigma > 0.0;
+        let valid = valid_sigma(sigma_near)
+            && valid_sigma(sigma_far)
+            && weight_near.is_finite()
+            && weight_far.is_finite()
+            && weight_far != 0.0;
+        self.falloff = if valid {
+            vec![(sigma_near, weight_near), (sigma_far, weight_far)]
+        } else {
+            Vec::new()
+        };
+    }
+
+    /// Terrain for `compute`, row-major: 0 = impassable, anything else passable.
+    /// With a mask set, influence spreads by step distance around impassable cells
+    /// instead of straight through them, so cells behind a wall get less (or none
+    /// beyond the cutoff), and impassable cells get none. An empty mask (the
+    /// default) restores the unobstructed falloff.
+    #[func]
+    fn set_passability(&mut self, passability: PackedByteArray) {
+        self.passability = passability.to_vec();
+    }
+
+    /// Compute influence for all players.
+    /// unit_positions_by_player: Dictionary { player_id: int -> Array[Vector2i] of grid positions }
+    /// territory_owner_grid: PackedInt32Array of size w*h, row-major, value = owner or -1
+    #[func]
+    fn compute(
+        &mut self,
+        unit_positions_by_player: Dictionary<Variant, Variant>,
+        territory_owner_grid: PackedInt32Array,
+        map_width: i32,
+        map_height: i32,
+    ) {
+        let w = map_width as usize;
+        let h = map_height as usize;
+        self.width = w;
+        self.height = h;
+
+        // Determine number of players
+        let mut max_pid: i32 = -1;
+        for key in unit_positions_by_player.keys_array().iter_shared() {
+            let pid = i32::from_variant(&key);
+            if pid > max_pid {
+                max_pid = pid;
+            }
+        }
+        for i in 0..territory_owner_grid.len() {
+            let v = territory_owner_grid[i];
+            if v > max_pid {
+                max_pid = v;
+            }
+        }
+        let np = (max_pid + 1).max(0) as usize;
+        self.num_players = np;
+
+        // Raw per-player influence
+        let mut raw: Vec<Vec<f32>> = vec![vec![0.0; w * h]; np];
+
+        // Add unit influence
+        for key in unit_positions_by_player.keys_array().iter_shared() {
+            let pid = i32::from_variant(&key) as usize;
+            if pid >= np {
+                continue;
+            }
+            let val_variant = unit_positions_by_player.get(&key).unwrap();
+            let positions: Array<Vector2i> = Array::from_variant(&val_variant);
+            for pos in positions.iter_shared() {
+                self.stamp_source(&mut raw[pid], (pos.x, pos.y), UNIT_INFLUENCE);
+            }
+        }
+
+        // Add territory influence
+        for i in 0..territory_owner_grid.len() {
+            let owner = territory_owner_grid[i];
+            if owner < 0 || owner as usize >= np {
+                continue;
+            }
+            let cx = (i % w) as i32;
+            let cy = (i / w) as i32;
+            let pid = owner as usize;
+            self.stamp_source(&mut raw[pid], (cx, cy), TERRITORY_INFLUENCE);
+        }
+
+        self.raw = raw;
+        self.influence = vec![vec![0.0f32; w * h]; np];
+        self.refresh_net(0, 0, w as i32 - 1, h as i32 - 1);
+    }
+
+    /// Move one of `player_id`'s units from `from` to `to` in the stored raw influence
+    /// from the last `compute`, without updating the net grids; follow up with
+    /// `recompute_region` over the area where units moved.
+    #[func]
+    fn move_unit(&mut self, player_id: i32, from: Vector2i, to: Vector2i) {
+        let Some(pid) = usize::try_from(player_id)
+            .ok()
+            .filter(|&p| p < self.raw.len())
+        else {
+            return;
+        };
+        let mut grid = std::mem::take(&mut self.raw[pid]);
+        self.stamp_source(&mut grid, (from.x, from.y), -UNIT_INFLUENCE);
+        self.stamp_source(&mut grid, (to.x, to.y), UNIT_INFLUENCE);
+        self.raw[pid] = grid;
+    }
+
+    /// Recompute net influence after local changes (e.g. `move_unit`) whose sources
+    /// all lie in the offset rectangle `min`..=`max`. Only cells within the falloff
+    /// cutoff of that rectangle are refreshed; the result there matches a full
+    /// `compute` with the same sources.
+    #[func]
+    fn recompute_region(&mut self, min: Vector2i, max: Vector2i) {
+        let reach = self.falloff_cutoff();
+        let (w, h) = (self.width as i32, self.height as i32);
+        self.refresh_net(
+            (min.x - reach).max(0),
+            (min.y - reach).max(0),
+            (max.x + reach).min(w - 1),
+            (max.y + reach).min(h - 1),
+        );
+    }
+
+    #[func]
+    fn get_player_influence(&self, player_id: i32) -> PackedFloat32Array {
+        let pid = player_id as usize;
+        if pid < self.influence.len() {
+            PackedFloat32Array::from(self.influence[pid].as_slice())
+        } else {
+            PackedFloat32Array::new()
+        }
+    }
+
+    /// `get_player_influence` rescaled to [0, 1] over that player's own grid
+    /// (`(net - min) / (max - min)`), e.g. for feeding a shader. A flat grid maps
+    /// to all 0.5. Empty for an unknown player.
+    #[func]
+    fn get_player_influence_normalized(&self, player_id: i32) -> PackedFloat32Array {
+        let normalized = usize::try_from(player_id)
+            .ok()
+            .and_then(|pid| self.influence.get(pid))
+            .map(|grid| normalize_grid(grid))
+            .unwrap_or_default();
+        PackedFloat32Array::from(normalized.as_slice())
+    }
+
+    /// Random tile drawn with probability proportional to `player_id`'s positive net
+    /// influence (cells at or below zero are never picked), using `rng` so the AI
+    /// stays reproducible. Returns Vector2i(-1, -1) if no cell is positive.
+    #[func]
+    fn sample_by_influence(&mut self, player_id: i32, mut rng: Gd<GameRng>) -> Vector2i {
+        let u = rng.bind_mut().randf();
+        let w = self.width.max(1);
+        usize::try_from(player_id)
+            .ok()
+            .and_then(|pid| self.influence.get(pid))
+            .and_then(|grid| sample_positive(grid, u))
+            .map_or(Vector2i::new(-1, -1), |i| {
+                Vector2i::new((i % w) as i32, (i / w) as i32)
+            })
+    }
+
+    /// War front between two players: polylines along the hex edges separating cells
+    /// where `player_a`'s net influence exceeds `player_b`'s from cells where it does
+    /// not. Returns Array of PackedVector2Array in the `hex_to_pixel` layout (origin
+    /// at tile (0, 0)), with A's side on the right of the walking direction. A front
+    /// that closes on itself repeats its first point at the end.
+    #[func]
+    fn front_line(&self, player_a: i32, player_b: i32, layout_size: Vector2) -> Array<Variant> {
+        let mut result = Array::new();
+        for line in self.front_chains(player_a, player_b) {
+            let points: Vec<Vector2> = line
+                .iter()
+                .map(|&key| corner_pixel(key, layout_size))
+                .collect();
+            result.push(&Variant::from(PackedVector2Array::from(points.as_slice())));
+        }
+        result
+    }
+
+    /// Net influence of `player_id` at a single tile, or 0.0 for an out-of-bounds
+    /// tile or unknown player.
+    #[func]
+    fn get_influence_at(&self, player_id: i32, pos: Vector2i) -> f32 {
+        let Some(grid) = usize::try_from(player_id)
+            .ok()
+            .and_then(|pid| self.influence.get(pid))
+        else {
+            return 0.0;
+        };
+        grid_index((pos.x, pos.y), self.width as i32, self.height as i32)
+            .and_then(|i| grid.get(i).copied())
+            .unwrap_or(0.0)
+    }
+
+    /// Direction index (0-5, `hex_neighbors` order) from `pos` toward its neighbor with
+    /// the lowest net influence for `player_id`, i.e. toward the front. Ties go to
+    /// the lower direction index. -1 if the player is unknown or no neighbor is on
+    /// the map.
+    #[func]
+    fn influence_gradient_direction(&self, player_id: i32, pos: Vector2i) -> i32 {
+        usize::try_from(player_id)
+            .ok()
+            .and_then(|pid| self.influence.get(pid))
+            .and_then(|grid| {
+                downhill_direction(grid, self.width as i32, self.height as i32, (pos.x, pos.y))
+            })
+            .map_or(-1, |d| d as i32)
+    }
+
+    /// Number of snapshots kept by `push_snapshot` (default 8). Shrinking drops the
+    /// oldest ones.
+    #[func]
+    fn set_history_length(&mut self, length: i32) {
+        self.history_length = length.max(0) as usize;
+        while self.history.len() > self.history_length {
+            self.history.pop_front();
+        }
+    }
+
+    /// Store the current net influence grids, dropping the oldest snapshot once
+    /// the history is full. Call once per turn after `compute`.
+    #[func]
+    fn push_snapshot(&mut self) {
+        if self.history_length == 0 {
+            return;
+        }
+        if self.history.len() == self.history_length {
+            self.history.pop_front();
+        }
+        self.history.push_back(self.influence.clone());
+    }
+
+    /// Per-cell change in `player_id`'s net influence: current grid minus the
+    /// snapshot `turns_ago` pushes back (0 = most recent). Positive = gaining.
+    /// Returns an empty array if that snapshot or the player's grid is missing.
+    #[func]
+    fn influence_delta(&self, player_id: i32, turns_ago: i32) -> PackedFloat32Array {
+        let delta = self.delta_grid(player_id, turns_ago).unwrap_or_default();
+        PackedFloat32Array::from(delta.as_slice())
+    }
+}
+
+impl InfluenceMap {
+    /// Net influence (own - strongest enemy) from the raw grids for every cell in
+    /// the inclusive rectangle (x0, y0)..=(x1, y1).
+    fn refresh_net(&mut self, x0: i32, y0: i32, x1: i32, y1: i32) {
+        let w = self.width;
+        for y in y0.max(0)..=y1 {
+            for x in x0.max(0)..=x1 {
+                let i = y as usize * w + x as usize;
+                for pid in 0..self.raw.len() {
+                    let mut max_enemy = 0.0f32;
+                    for (other, raw_other) in self.raw.iter().enumerate() {
+                        if other != pid {
+                            max_enemy = max_enemy.max(raw_other[i]);
+                        }
+                    }
+                    self.influence[pid][i] = self.raw[pid][i] - max_enemy;
+                }
+            }
+        }
+    }
+
+    /// Farthest a single source reaches in offset x or y under the current falloff.
+    fn falloff_cutoff(&self) -> i32 {
+        if self.falloff.is_empty() {
+            return (DEFAULT_INFLUENCE_SIGMA * 3.0) as i32;
+        }
+        self.falloff
+            .iter()
+            .map(|&(sigma, _)| (sigma * 3.0) as i32)
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Add one source's influence to `grid` using the configured falloff shape.
+    fn stamp_source(&self, grid: &mut [f32], center: (i32, i32), strength: f32) {
+        let default = [(DEFAULT_INFLUENCE_SIGMA, 1.0)];
+        let components = if self.falloff.is_empty() {
+            &default[..]
+        } else {
+            &self.falloff[..]
+        };
+        for &(sigma, weight) in components {
+            add_falloff(
+                grid,
+                self.width,
+                self.height,
+                center,
+                strength * weight,
+                sigma,
+                self.use_hex_distance,
+                &self.passability,
+            );
+        }
+    }
+
+    /// `front_line` polylines as `corner_key` sequences; closed fronts repeat the
+    /// first point. Empty if either player is unknown.
+    fn front_chains(&self, player_a: i32, player_b: i32) -> Vec<Vec<(i32, i32)>> {
+        let grid = |pid: i32| {
+            usize::try_from(pid)
+                .ok()
+                .and_then(|p| self.influence.get(p))
+        };
+        let (Some(a), Some(b)) = (grid(player_a), grid(player_b)) else {
+            return Vec::new();
+        };
+        let (w, h) = (self.width as i32, self.height as i32);
+        let lead = |pos| grid_index(pos, w, h).and_then(|i| Some(a.get(i)? - b.get(i)?));
+        let edges = boundary_edges(
+            |pos| lead(pos).is_some_and(|d| d > 0.0),
+            |pos| lead(pos).is_some_and(|d| d <= 0.0),
+            w,
+            h,
+        );
+        edge_chains(&edges)
+            .into_iter()
+            .map(|(mut points, closed)| {
+                if closed {
+                    points.push(points[0]);
+                }
+                points
+            })
+            .collect()
+    }
+
+    fn delta_grid(&self, player_id: i32, turns_ago: i32) -> Option<Vec<f32>> {
+        let pid = usize::try_from(player_id).ok()?;
+        let back = usize::try_from(turns_ago).ok()?;
+        let past = self
+            .history
+            .len()
+            .checked_sub(back + 1)
+            .and_then(|i| self.history.get(i))?
+            .get(pid)?;
+        let now = self.influence.get(pid)?;
+        if now.len() != past.len() {
+            return None;
+        }
+        Some(now.iter().zip(past).map(|(n, p)| n - p).collect())
+    }
+}
+
+const DEFAULT_INFLUENCE_SIGMA: f32 = 4.0;
+const UNIT_INFLUENCE: f32 = 2.0;
+const TERRITORY_INFLUENCE: f32 = 0.5;
+
+/// Add a Gaussian bump of height `strength` centered on `center` to a row-major grid,
+/// cut off at 3 sigma. Distance is hex distance when `use_hex_distance`, else the
+/// offset-space `dx*dx + dy*dy`.
+#[allow(clippy::too_many_arguments)]
+fn add_falloff(
+    grid: &mut [f32],
+    w: usize,
+    h: usize,
+    center: (i32, i32),
+    strength: f32,
+    sigma: f32,
+    use_hex_distance: bool,
+    passability: &[u8],
+) {
+    let two_sigma_sq = 2.0 * sigma * sigma;
+    let max_range = (sigma * 3.0) as i32; // cutoff at 3 sigma
+
+    if !passability.is_empty() {
+        // Step distance around impassable cells, out to the cutoff
+        let passable = |i: usize| passability.get(i) != Some(&0);
+        let Some(start) = grid_index(center, w as i32, h as i32).filter(|&i| passable(i)) else {
+            return;
+        };
+        let mut seen = HashSet::from([start]);
+        let mut frontier = vec![center];
+        for d in 0..=max_range {
+            let weight = strength * (-((d * d) as f32) / two_sigma_sq).exp();
+            let mut next = Vec::new();
+            for &(x, y) in &frontier {
+                grid[y as usize * w + x as usize] += weight;
+                for n in hex_neighbors_vec(x, y) {
+                    if let Some(ni) = grid_index(n, w as i32, h as i32) {
+                        if passable(ni) && seen.insert(ni) {
+                            next.push(n);
+                        }
+                    }
+                }
+            }
+            frontier = next;
+        }
+        return;
+    }
+
+    let (cx, cy) = center;
+    for dy in -max_range..=max_range {
+        for dx in -max_range..=max_range {
+            let nx = cx + dx;
+            let ny = cy + dy;
+            if nx < 0 || ny < 0 || nx >= w as i32 || ny >= h as i32 {
+                continue;
+            }
+            let dist_sq = if use_hex_distance {
+                let d = hex_distance_xy(center, (nx, ny));
+                if d > max_range {
+                    continue;
+                }
+                (d * d) as f32
+            } else {
+                (dx * dx + dy * dy) as f32
+            };
+            grid[ny as usize * w + nx as usize] += strength * (-dist_sq / two_sigma_sq).exp();
+        }
+    }
+}
+
+fn downhill_direction(grid: &[f32], w: i32, h: i32, pos: (i32, i32)) -> Option<usize> {
+    let mut best: Option<(usize, f32)> = None;
+    for (d, n) in hex_neighbors_vec(pos.0, pos.1).into_iter().enumerate() {
+        let Some(v) = grid_index(n, w, h).and_then(|i| grid.get(i).copied()) else {
+            continue;
+        };
+        if best.is_none_or(|(_, lowest)| v < lowest) {
+            best = Some((d, v));
+        }
+    }
+    best.map(|(d, _)| d)
+}
+
+/// Index picked by walking the cumulative sum of positive weights to `u * total`,
+/// with `u` in [0, 1). None if no weight is positive.
+fn sample_positive(weights: &[f32], u: f64) -> Option<usize> {
+    let total: f64 = weights.iter().map(|&v| v.max(0.0) as f64).sum();
+    if total <= 0.0 {
+        return None;
+    }
+    let target = u * total;
+    let mut acc = 0.0;
+    let mut last = None;
+    for (i, &v) in weights.iter().enumerate() {
+        if v <= 0.0 {
+            continue;
+        }
+        acc += v as f64;
+        last = Some(i);
+        if target < acc {
+            return last;
+        }
+    }
+    last // float rounding left `target` at the very end
+}
+
+/// Min-max rescale to [0, 1]; all 0.5 when every value is equal.
+fn normalize_grid(grid: &[f32]) -> Vec<f32> {
+    let min = grid.iter().copied().fold(f32::INFINITY, f32::min);
+    let max = grid.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+    let range = max - min;
+    if range <= 0.0 {
+        return vec![0.5; grid.len()];
+    }
+    grid.iter().map(|&v| (v - min) / range).collect()
+}
+
+// ============================================================
+// 2. TerritoryFrontier
+// ============================================================
+
+#[derive(GodotClass)]
+#[class(base=RefCounted, init)]
+pub struct TerritoryFrontier {
+    // Tracked state for incremental updates (`set_owner_grid` / `update_tile`)
+    owner_grid: Vec<i32>,
+    width: i32,
+    height: i32,
+    tracked_player: i32,
+    /// Row-major indices of the tracked player's frontier tiles.
+    frontier: BTreeSet<usize>,
+}
+
+#[godot_api]
+impl TerritoryFrontier {
+    /// Returns Array[Vector2i] of frontier tiles (adjacent to player's territory, not owned by player, not water=3).
+    #[func]
+    fn get_frontier(
+        &self,
+        owner_grid: PackedInt32Array,
+        player_id: i32,
+        map_width: i32,
+        map_height: i32,
+    ) -> Array<Vector2i> {
+        let w = map_width as usize;
+        let h = map_height as usize;
+        let mut frontier_set = std::collections::HashSet::new();
+        let mut result = Array::new();
 
         for i in 0..owner_grid.len().min(w * h) {
             if owner_grid[i] != player_id {
                 continue;
             }
-            let x = (i % w) as i32;
-            let y = (i / w) as i32;
-            for (nx, ny) in hex_neighbors_vec(x, y) {
-                if nx < 0 || ny < 0 || nx >= map_width || ny >= map_height {
-                    continue;
-                }
-                let ni = ny as usize * w + nx as usize;
-                if ni >= owner_grid.len() {
+            let x = (i % w) as i32;
+            let y = (i / w) as i32;
+            for (nx, ny) in hex_neighbors_vec(x, y) {
+                if nx < 0 || ny < 0 || nx >= map_width || ny >= map_height {
+                    continue;
+                }
+                let ni = ny as usize * w + nx as usize;
+                if ni >= owner_grid.len() {
+                    continue;
+                }
+                let owner = owner_grid[ni];
+                if owner == player_id {
+                    continue;
+                }
+                // Skip water (type check not available here — caller filters or we accept all non-owned)
+                if frontier_set.insert((nx, ny)) {
+                    result.push(Vector2i::new(nx, ny));
+                }
+            }
+        }
+        result
+    }
+
+    /// Every tile owned by `player_id`, in row-major order.
+    #[func]
+    fn owned_tiles(
+        &self,
+        owner_grid: PackedInt32Array,
+        player_id: i32,
+        map_width: i32,
+    ) -> Array<Vector2i> {
+        let mut result = Array::new();
+        for (x, y) in owned_positions(owner_grid.as_slice(), player_id, map_width) {
+            result.push(Vector2i::new(x, y));
+        }
+        result
+    }
+
+    /// Border pressure from `enemy_id` on `player_id`: each of the player's border
+    /// tiles (owned, with at least one in-bounds neighbor they don't own) mapped to
+    /// how many of its neighbors `enemy_id` owns, 0 included. Dictionary
+    /// { Vector2i -> int } in row-major order.
+    #[func]
+    fn border_pressure(
+        &self,
+        owner_grid: PackedInt32Array,
+        player_id: i32,
+        enemy_id: i32,
+        map_width: i32,
+        map_height: i32,
+    ) -> Dictionary<Vector2i, i32> {
+        let mut result = Dictionary::new();
+        for ((x, y), count) in border_pressure_counts(
+            owner_grid.as_slice(),
+            player_id,
+            enemy_id,
+            map_width,
+            map_height,
+        ) {
+            result.set(Vector2i::new(x, y), count);
+        }
+        result
+    }
+
+    /// Tiles owned by each player 0..`num_players` in one pass over `owner_grid`;
+    /// unowned (-1) and out-of-range owners are ignored.
+    #[func]
+    fn owner_histogram(&self, owner_grid: PackedInt32Array, num_players: i32) -> PackedInt32Array {
+        let counts = owner_counts(owner_grid.as_slice(), num_players.max(0) as usize);
+        PackedInt32Array::from(counts.as_slice())
+    }
+
+    /// Copy of `owner_grid` with every owner listed in `mapping` replaced by its
+    /// mapped value (e.g. an eliminated player -> conqueror, or -> -1 for neutral).
+    /// Unlisted owners are kept. The mapping is applied once, not chained.
+    #[func]
+    fn remap_owners(
+        &self,
+        owner_grid: PackedInt32Array,
+        mapping: Dictionary<i32, i32>,
+    ) -> PackedInt32Array {
+        let mapping: HashMap<i32, i32> = mapping.iter_shared().collect();
+        let remapped = remapped_owners(owner_grid.as_slice(), &mapping);
+        PackedInt32Array::from(remapped.as_slice())
+    }
+
+    /// Number of tiles owned by `player_id`.
+    #[func]
+    fn owned_count(&self, owner_grid: PackedInt32Array, player_id: i32) -> i32 {
+        owner_grid
+            .as_slice()
+            .iter()
+            .filter(|&&o| o == player_id)
+            .count() as i32
+    }
+
+    /// Start tracking `player_id`'s frontier on `owner_grid` for `update_tile`.
+    /// Returns the frontier tiles in row-major order (the same set as `get_frontier`).
+    #[func]
+    fn set_owner_grid(
+        &mut self,
+        owner_grid: PackedInt32Array,
+        player_id: i32,
+        map_width: i32,
+        map_height: i32,
+    ) -> Array<Vector2i> {
+        self.track_owner_grid(owner_grid.as_slice(), player_id, map_width, map_height);
+        self.frontier_array()
+    }
+
+    /// Change the owner of tile `index` (row-major) and return the tracked player's
+    /// frontier tiles in row-major order. Only the tile and its neighbors are
+    /// re-checked. Out-of-range indices leave the state unchanged.
+    #[func]
+    fn update_tile(&mut self, index: i32, new_owner: i32) -> Array<Vector2i> {
+        self.set_tile_owner(index, new_owner);
+        self.frontier_array()
+    }
+
+    /// Unowned, passable tiles adjacent to `player_id`'s territory, ranked by the
+    /// cheapest movement cost to reach them from `capital` (Dijkstra over `costs`,
+    /// default 1.0, avoiding `impassable_types`). Returns Array of [Vector2i, float]
+    /// pairs sorted ascending by cost; unreachable frontier tiles are left out, and
+    /// an off-map `capital` gives an empty Array.
+    #[func]
+    #[allow(clippy::too_many_arguments)]
+    fn ranked_frontier(
+        &self,
+        owner_grid: PackedInt32Array,
+        player_id: i32,
+        capital: Vector2i,
+        costs: Dictionary<Vector2i, f64>,
+        tile_types: PackedInt32Array,
+        impassable_types: PackedInt32Array,
+        map_width: i32,
+        map_height: i32,
+    ) -> Array<Variant> {
+        let grid = PathGrid {
+            blocked: impassable_tiles(
+                tile_types.as_slice(),
+                impassable_types.as_slice(),
+                map_width,
+                map_height,
+            ),
+            costs: costs
+                .iter_shared()
+                .map(|(pos, cost)| ((pos.x, pos.y), cost))
+                .collect(),
+            max_distance: i32::MAX,
+        };
+        let ranked = frontier_by_cost(
+            &grid,
+            owner_grid.as_slice(),
+            player_id,
+            (capital.x, capital.y),
+            map_width,
+            map_height,
+        );
+        let mut result = Array::new();
+        for ((x, y), cost) in ranked {
+            let mut entry = Array::<Variant>::new();
+            entry.push(&Variant::from(Vector2i::new(x, y)));
+            entry.push(&Variant::from(cost));
+            result.push(&Variant::from(entry));
+        }
+        result
+    }
+
+    /// Frontier tiles `player_id` can claim now: adjacent to their territory, in
+    /// bounds, not of an `impassable_types` type, with the player's net `influence`
+    /// above `influence_threshold`, and either unowned or owned by a player who is
+    /// weaker there (net influence > 0). Row-major order.
+    #[func]
+    #[allow(clippy::too_many_arguments)]
+    fn claimable_tiles(
+        &self,
+        owner_grid: PackedInt32Array,
+        influence: PackedFloat32Array,
+        player_id: i32,
+        tile_types: PackedInt32Array,
+        impassable_types: PackedInt32Array,
+        influence_threshold: f32,
+        map_width: i32,
+        map_height: i32,
+    ) -> Array<Vector2i> {
+        let tiles = claimable(
+            owner_grid.as_slice(),
+            influence.as_slice(),
+            player_id,
+            tile_types.as_slice(),
+            impassable_types.as_slice(),
+            influence_threshold,
+            map_width,
+            map_height,
+        );
+        path_to_array(Some(tiles))
+    }
+
+    /// Size of `player_id`'s territory pieces: Dictionary { "largest_area": int (tiles),
+    /// "largest_perimeter": int (edges of that region facing anything else, map edge
+    /// included), "num_regions": int (hex-connected components) }. The first region
+    /// in row-major order wins ties for largest.
+    #[func]
+    fn region_stats(
+        &self,
+        owner_grid: PackedInt32Array,
+        player_id: i32,
+        map_width: i32,
+        map_height: i32,
+    ) -> Dictionary<Variant, Variant> {
+        let (area, perimeter, regions) =
+            owned_region_stats(owner_grid.as_slice(), player_id, map_width, map_height);
+        let mut dict = Dictionary::new();
+        dict.set(&Variant::from("largest_area"), &Variant::from(area));
+        dict.set(
+            &Variant::from("largest_perimeter"),
+            &Variant::from(perimeter),
+        );
+        dict.set(&Variant::from("num_regions"), &Variant::from(regions));
+        dict
+    }
+
+    /// Number of hex edges where a tile of `player_a` touches a tile of `player_b`.
+    #[func]
+    fn shared_border_length(
+        &self,
+        owner_grid: PackedInt32Array,
+        player_a: i32,
+        player_b: i32,
+        map_width: i32,
+        map_height: i32,
+    ) -> i32 {
+        shared_border_edges(
+            owner_grid.as_slice(),
+            player_a,
+            player_b,
+            map_width,
+            map_height,
+        )
+    }
+
+    /// Ordered outlines of `player_id`'s territory for rendering. Returns an Array of
+    /// PackedVector2Array, one closed loop of hex corner points per boundary (the
+    /// last point connects back to the first): one per connected region plus one
+    /// per hole. Outer loops run clockwise on screen, holes counter-clockwise.
+    /// Points use the `hex_to_pixel` layout with the origin at tile (0, 0).
+    #[func]
+    fn trace_border(
+        &self,
+        owner_grid: PackedInt32Array,
+        player_id: i32,
+        layout_size: Vector2,
+        map_width: i32,
+        map_height: i32,
+    ) -> Array<Variant> {
+        let mut result = Array::new();
+        for border in border_loops(owner_grid.as_slice(), player_id, map_width, map_height) {
+            let points: Vec<Vector2> = border
+                .iter()
+                .map(|&key| corner_pixel(key, layout_size))
+                .collect();
+            result.push(&Variant::from(PackedVector2Array::from(points.as_slice())));
+        }
+        result
+    }
+
+    /// Morphological dilation: a tile becomes set if it or any hex neighbor is set,
+    /// repeated `iterations` times. `mask` is row-major, nonzero = set; output is 0/1.
+    #[func]
+    fn dilate(
+        &self,
+        mask: PackedByteArray,
+        iterations: i32,
+        map_width: i32,
+        map_height: i32,
+    ) -> PackedByteArray {
+        let out = morph_mask(mask.as_slice(), iterations, map_width, map_height, true);
+        PackedByteArray::from(out.as_slice())
+    }
+
+    /// Morphological erosion: a tile stays set only if it and every in-bounds hex
+    /// neighbor are set, repeated `iterations` times. Combine with `dilate` for
+    /// open/close.
+    #[func]
+    fn erode(
+        &self,
+        mask: PackedByteArray,
+        iterations: i32,
+        map_width: i32,
+        map_height: i32,
+    ) -> PackedByteArray {
+        let out = morph_mask(mask.as_slice(), iterations, map_width, map_height, false);
+        PackedByteArray::from(out.as_slice())
+    }
+
+    /// 0/1 mask of tiles within hex `radius` of at least one city, e.g. for supply
+    /// or happiness range checks. `cities` holds grid positions, rounded to the
+    /// nearest tile.
+    #[func]
+    fn coverage_mask(
+        &self,
+        cities: PackedVector2Array,
+        radius: i32,
+        map_width: i32,
+        map_height: i32,
+    ) -> PackedByteArray {
+        let centers: Vec<(i32, i32)> = cities
+            .as_slice()
+            .iter()
+            .map(|c| (c.x.round() as i32, c.y.round() as i32))
+            .collect();
+        let mask = coverage_grid(&centers, radius, map_width, map_height);
+        PackedByteArray::from(mask.as_slice())
+    }
+
+    /// 0/1 mask of tiles within `supply_range` moves of any depot, walking around
+    /// `blocked` (multi-source BFS), so walls cut supply lines that a plain radius
+    /// check would let through. `depots` holds grid positions; blocked tiles are
+    /// never supplied.
+    #[func]
+    fn supply_mask(
+        &self,
+        depots: PackedVector2Array,
+        supply_range: i32,
+        blocked: Array<Vector2i>,
+        map_width: i32,
+        map_height: i32,
+    ) -> PackedByteArray {
+        let blocked_mask = blocked_grid(&blocked, map_width, map_height);
+        let depots: Vec<(i32, i32)> = depots
+            .as_slice()
+            .iter()
+            .map(|p| (p.x.round() as i32, p.y.round() as i32))
+            .collect();
+        let mask = supply_grid(&depots, supply_range, &blocked_mask, map_width, map_height);
+        PackedByteArray::from(mask.as_slice())
+    }
+
+    /// Step-distance "nearest city" owner grid via multi-source BFS around `blocked`.
+    /// Each tile gets `city_owners` of its nearest city, -2 if cities of different
+    /// owners tie for nearest, or -1 if blocked or unreachable. `cities` are grid
+    /// positions, rounded to the nearest tile.
+    #[func]
+    fn nearest_city_labels(
+        &self,
+        cities: PackedVector2Array,
+        city_owners: PackedInt32Array,
+        blocked: Array<Vector2i>,
+        map_width: i32,
+        map_height: i32,
+    ) -> PackedInt32Array {
+        let seeds: Vec<((i32, i32), i32)> = cities
+            .as_slice()
+            .iter()
+            .zip(city_owners.as_slice())
+            .map(|(c, &o)| ((c.x.round() as i32, c.y.round() as i32), o))
+            .collect();
+        let blocked_mask = blocked_grid(&blocked, map_width, map_height);
+        let labels = nearest_owner_labels(&seeds, &blocked_mask, map_width, map_height);
+        PackedInt32Array::from(labels.as_slice())
+    }
+
+    /// Weighted Voronoi owner grid: each passable tile goes to `owner_of_city` of the
+    /// city minimizing `hex_distance / strength`; ties go to the earlier city.
+    /// Tiles whose type is in `impassable_types`, and all tiles when no city has a
+    /// positive strength, get -1. `cities` are grid positions, rounded to the
+    /// nearest tile.
+    #[allow(clippy::too_many_arguments)]
+    #[func]
+    fn assign_territory(
+        &self,
+        cities: PackedVector2Array,
+        strengths: PackedFloat32Array,
+        owner_of_city: PackedInt32Array,
+        tile_types: PackedInt32Array,
+        impassable_types: PackedInt32Array,
+        map_width: i32,
+        map_height: i32,
+    ) -> PackedInt32Array {
+        let seeds: Vec<((i32, i32), f32, i32)> = cities
+            .as_slice()
+            .iter()
+            .zip(strengths.as_slice())
+            .zip(owner_of_city.as_slice())
+            .map(|((c, &s), &o)| ((c.x.round() as i32, c.y.round() as i32), s, o))
+            .collect();
+        let owners = weighted_territory(
+            &seeds,
+            tile_types.as_slice(),
+            impassable_types.as_slice(),
+            map_width,
+            map_height,
+        );
+        PackedInt32Array::from(owners.as_slice())
+    }
+}
+
+impl TerritoryFrontier {
+    fn track_owner_grid(
+        &mut self,
+        owner_grid: &[i32],
+        player_id: i32,
+        map_width: i32,
+        map_height: i32,
+    ) {
+        self.owner_grid = owner_grid.to_vec();
+        self.owner_grid
+            .resize((map_width.max(0) * map_height.max(0)) as usize, -1);
+        self.width = map_width.max(0);
+        self.height = map_height.max(0);
+        self.tracked_player = player_id;
+        self.frontier.clear();
+        for i in 0..self.owner_grid.len() {
+            self.refresh_frontier_tile(i);
+        }
+    }
+
+    fn set_tile_owner(&mut self, index: i32, new_owner: i32) {
+        let Some(cell) = usize::try_from(index)
+            .ok()
+            .and_then(|i| self.owner_grid.get_mut(i))
+        else {
+            return;
+        };
+        *cell = new_owner;
+        let index = index as usize;
+        let (x, y) = (index as i32 % self.width, index as i32 / self.width);
+        self.refresh_frontier_tile(index);
+        for n in hex_neighbors_vec(x, y) {
+            if let Some(ni) = grid_index(n, self.width, self.height) {
+                self.refresh_frontier_tile(ni);
+            }
+        }
+    }
+
+    /// Recompute whether tile `index` is on the tracked player's frontier.
+    fn refresh_frontier_tile(&mut self, index: usize) {
+        let (x, y) = (index as i32 % self.width, index as i32 / self.width);
+        let player = self.tracked_player;
+        let on_frontier = self.owner_grid[index] != player
+            && hex_neighbors_vec(x, y).into_iter().any(|n| {
+                grid_index(n, self.width, self.height)
+                    .is_some_and(|ni| self.owner_grid[ni] == player)
+            });
+        if on_frontier {
+            self.frontier.insert(index);
+        } else {
+            self.frontier.remove(&index);
+        }
+    }
+
+    fn frontier_array(&self) -> Array<Vector2i> {
+        let mut result = Array::new();
+        for &i in &self.frontier {
+            result.push(Vector2i::new(i as i32 % self.width, i as i32 / self.width));
+        }
+        result
+    }
+}
+
+/// Multi-source BFS labeling each reachable tile with the owner of its nearest seed
+/// (by step distance). Tiles equally near to seeds of different owners get -2;
+/// blocked and unreachable tiles get -1. Seeds are (position, owner).
+fn nearest_owner_labels(
+    seeds: &[((i32, i32), i32)],
+    blocked: &[bool],
+    map_width: i32,
+    map_height: i32,
+) -> Vec<i32> {
+    let n = (map_width.max(0) * map_height.max(0)) as usize;
+    let mut dist = vec![-1; n];
+    let mut label = vec![-1; n];
+    let mut queue = std::collections::VecDeque::new();
+    for &(pos, owner) in seeds {
+        let Some(i) = grid_index(pos, map_width, map_height) else {
+            continue;
+        };
+        if dist[i] < 0 {
+            dist[i] = 0;
+            label[i] = owner;
+            queue.push_back(pos);
+        } else if label[i] != owner {
+            label[i] = -2;
+        }
+    }
+    // FIFO order finishes every tile at distance d before expanding any at d + 1,
+    // so a tile's label is final by the time it is expanded.
+    while let Some((x, y)) = queue.pop_front() {
+        let i = grid_index((x, y), map_width, map_height).unwrap();
+        for nb in hex_neighbors_vec(x, y) {
+            let Some(ni) = grid_index(nb, map_width, map_height) else {
+                continue;
+            };
+            if blocked.get(ni) == Some(&true) {
+                continue;
+            }
+            if dist[ni] < 0 {
+                dist[ni] = dist[i] + 1;
+                label[ni] = label[i];
+                queue.push_back(nb);
+            } else if dist[ni] == dist[i] + 1 && label[ni] != label[i] {
+                label[ni] = -2;
+            }
+        }
+    }
+    label
+}
+
+/// Owner grid for `assign_territory`. Seeds are (position, strength, owner).
+fn weighted_territory(
+    seeds: &[((i32, i32), f32, i32)],
+    tile_types: &[i32],
+    impassable_types: &[i32],
+    map_width: i32,
+    map_height: i32,
+) -> Vec<i32> {
+    let mut owners = vec![-1; (map_width.max(0) * map_height.max(0)) as usize];
+    for y in 0..map_height {
+        for x in 0..map_width {
+            let i = (y * map_width + x) as usize;
+            if tile_types
+                .get(i)
+                .is_some_and(|t| impassable_types.contains(t))
+            {
+                continue;
+            }
+            let mut best = f32::INFINITY;
+            for &(pos, strength, owner) in seeds {
+                if strength <= 0.0 {
+                    continue;
+                }
+                let score = hex_distance_xy(pos, (x, y)) as f32 / strength;
+                if score < best {
+                    best = score;
+                    owners[i] = owner;
+                }
+            }
+        }
+    }
+    owners
+}
+
+/// Union of the hex disks of `radius` around each center, walking each disk
+/// rather than testing every tile against every city.
+fn coverage_grid(centers: &[(i32, i32)], radius: i32, map_width: i32, map_height: i32) -> Vec<u8> {
+    let mut mask = vec![0u8; (map_width.max(0) * map_height.max(0)) as usize];
+    for &center in centers {
+        for pos in hex_disk(center, radius) {
+            if let Some(i) = grid_index(pos, map_width, map_height) {
+                mask[i] = 1;
+            }
+        }
+    }
+    mask
+}
+
+fn remapped_owners(owner_grid: &[i32], mapping: &HashMap<i32, i32>) -> Vec<i32> {
+    owner_grid
+        .iter()
+        .map(|o| mapping.get(o).copied().unwrap_or(*o))
+        .collect()
+}
+
+fn owned_positions(owner_grid: &[i32], player_id: i32, map_width: i32) -> Vec<(i32, i32)> {
+    let w = map_width.max(1) as usize;
+    owner_grid
+        .iter()
+        .enumerate()
+        .filter(|&(_, &owner)| owner == player_id)
+        .map(|(i, _)| ((i % w) as i32, (i / w) as i32))
+        .collect()
+}
+
+fn border_pressure_counts(
+    owner_grid: &[i32],
+    player_id: i32,
+    enemy_id: i32,
+    map_width: i32,
+    map_height: i32,
+) -> Vec<((i32, i32), i32)> {
+    let owner_at = |pos| grid_index(pos, map_width, map_height).and_then(|i| owner_grid.get(i));
+    let mut result = Vec::new();
+    for (x, y) in owned_positions(owner_grid, player_id, map_width) {
+        if y >= map_height {
+            break;
+        }
+        let neighbors: Vec<i32> = hex_neighbors_vec(x, y)
+            .into_iter()
+            .filter_map(|n| owner_at(n).copied())
+            .collect();
+        if neighbors.iter().any(|&o| o != player_id) {
+            let pressure = neighbors.iter().filter(|&&o| o == enemy_id).count() as i32;
+            result.push(((x, y), pressure));
+        }
+    }
+    result
+}
+
+fn owner_counts(owner_grid: &[i32], num_players: usize) -> Vec<i32> {
+    let mut counts = vec![0; num_players];
+    for &owner in owner_grid {
+        if let Some(count) = usize::try_from(owner).ok().and_then(|o| counts.get_mut(o)) {
+            *count += 1;
+        }
+    }
+    counts
+}
+
+fn supply_grid(
+    depots: &[(i32, i32)],
+    supply_range: i32,
+    blocked: &[bool],
+    map_width: i32,
+    map_height: i32,
+) -> Vec<u8> {
+    let open = |i: usize| blocked.get(i) != Some(&true);
+    let sources: Vec<(i32, i32)> = depots
+        .iter()
+        .copied()
+        .filter(|&d| grid_index(d, map_width, map_height).is_some_and(open))
+        .collect();
+    bfs_distance_grid(&sources, map_width, map_height, open)
+        .into_iter()
+        .map(|d| (d >= 0 && d <= supply_range) as u8)
+        .collect()
+}
+
+/// Dilate (`grow`) or erode a 0/1 mask. Off-map neighbors are ignored either way,
+/// so erosion does not eat in from the map edge.
+fn morph_mask(
+    mask: &[u8],
+    iterations: i32,
+    map_width: i32,
+    map_height: i32,
+    grow: bool,
+) -> Vec<u8> {
+    let mut cur: Vec<u8> = mask.iter().map(|&v| (v != 0) as u8).collect();
+    for _ in 0..iterations.max(0) {
+        let mut next = cur.clone();
+        for y in 0..map_height {
+            for x in 0..map_width {
+                let Some(i) = grid_index((x, y), map_width, map_height).filter(|&i| i < cur.len())
+                else {
+                    continue;
+                };
+                let mut neighbors = hex_neighbors_vec(x, y)
+                    .into_iter()
+                    .filter_map(|n| grid_index(n, map_width, map_height))
+                    .filter_map(|ni| cur.get(ni));
+                next[i] = if grow {
+                    (cur[i] == 1 || neighbors.any(|&v| v == 1)) as u8
+                } else {
+                    (cur[i] == 1 && neighbors.all(|&v| v == 1)) as u8
+                };
+            }
+        }
+        cur = next;
+    }
+    cur
+}
+
+/// Corner `i` of a tile (the `hex_corners` order) as integer lattice coordinates:
+/// x in half hex radii, y in units of sqrt(3)/2 hex radii. Neighboring tiles
+/// produce identical keys for shared corners.
+fn corner_key(pos: (i32, i32), corner: usize) -> (i32, i32) {
+    const DX: [i32; 6] = [2, 1, -1, -2, -1, 1];
+    const DY: [i32; 6] = [0, 1, 1, 0, -1, -1];
+    let (q, r) = to_axial(Vector2i::new(pos.0, pos.1));
+    (3 * q + DX[corner], 2 * r + q + DY[corner])
+}
+
+/// Pixel position of a `corner_key` in the `hex_to_pixel` layout with the origin at
+/// tile (0, 0).
+fn corner_pixel(key: (i32, i32), layout_size: Vector2) -> Vector2 {
+    let half_height = 3f32.sqrt() / 2.0;
+    Vector2::new(
+        key.0 as f32 * 0.5 * layout_size.x,
+        key.1 as f32 * half_height * layout_size.y,
+    )
+}
+
+/// Directed hex edge from one `corner_key` to the next.
+type HexEdge = ((i32, i32), (i32, i32));
+
+/// Directed hex edges (as `corner_key` pairs) between each in-bounds tile for which
+/// `inside` holds and each neighbor for which `outside` holds. Every edge runs from
+/// corner `e` to `e + 1` of the inside tile, i.e. clockwise around it on screen.
+fn boundary_edges(
+    inside: impl Fn((i32, i32)) -> bool,
+    outside: impl Fn((i32, i32)) -> bool,
+    map_width: i32,
+    map_height: i32,
+) -> Vec<HexEdge> {
+    let mut edges = Vec::new();
+    for y in 0..map_height {
+        for x in 0..map_width {
+            if !inside((x, y)) {
+                continue;
+            }
+            let neighbors = hex_neighbors_vec(x, y);
+            for e in 0..6 {
+                // Edge e (corner e to e + 1) faces neighbor direction (6 - e) % 6
+                if outside(neighbors[(6 - e) % 6]) {
+                    edges.push((corner_key((x, y), e), corner_key((x, y), (e + 1) % 6)));
+                }
+            }
+        }
+    }
+    edges
+}
+
+/// Join directed edges end-to-start into polylines, returned with whether each is
+/// closed. Open chains include both end points; closed loops list each corner once
+/// (the last point connects back to the first). Relies on every corner having at
+/// most one outgoing and one incoming edge, which holds for `boundary_edges`.
+fn edge_chains(edges: &[HexEdge]) -> Vec<(Vec<(i32, i32)>, bool)> {
+    let next: HashMap<(i32, i32), usize> = edges
+        .iter()
+        .enumerate()
+        .map(|(i, &(from, _))| (from, i))
+        .collect();
+    let ends: HashSet<(i32, i32)> = edges.iter().map(|&(_, to)| to).collect();
+    let mut used = vec![false; edges.len()];
+    let mut chains = Vec::new();
+    // Open chains first, from edges nothing leads into
+    for first in 0..edges.len() {
+        if ends.contains(&edges[first].0) {
+            continue;
+        }
+        let mut points = vec![edges[first].0];
+        let mut cur = Some(first);
+        while let Some(i) = cur {
+            used[i] = true;
+            points.push(edges[i].1);
+            cur = next.get(&edges[i].1).copied();
+        }
+        chains.push((points, false));
+    }
+    for first in 0..edges.len() {
+        if used[first] {
+            continue;
+        }
+        let mut points = Vec::new();
+        let mut cur = first;
+        while !used[cur] {
+            used[cur] = true;
+            points.push(edges[cur].0);
+            cur = next[&edges[cur].1];
+        }
+        chains.push((points, true));
+    }
+    chains
+}
+
+/// Closed boundary loops of `player_id`'s territory as `corner_key` sequences.
+/// Outer loops wind clockwise on screen (y down) and holes counter-clockwise.
+fn border_loops(
+    owner_grid: &[i32],
+    player_id: i32,
+    map_width: i32,
+    map_height: i32,
+) -> Vec<Vec<(i32, i32)>> {
+    let owned = |pos| {
+        grid_index(pos, map_width, map_height).and_then(|i| owner_grid.get(i)) == Some(&player_id)
+    };
+    let edges = boundary_edges(owned, |n| !owned(n), map_width, map_height);
+    edge_chains(&edges)
+        .into_iter()
+        .map(|(points, _)| points)
+        .collect()
+}
+
+/// Tiles whose type is in `impassable_types`, plus a one-tile ring just outside
+/// the map so searches stay in bounds.
+fn impassable_tiles(
+    tile_types: &[i32],
+    impassable_types: &[i32],
+    map_width: i32,
+    map_height: i32,
+) -> HashSet<(i32, i32)> {
+    let mut blocked = HashSet::new();
+    for y in -1..=map_height {
+        for x in -1..=map_width {
+            let impassable = match grid_index((x, y), map_width, map_height) {
+                Some(i) => tile_types
+                    .get(i)
+                    .is_some_and(|t| impassable_types.contains(t)),
+                None => true,
+            };
+            if impassable {
+                blocked.insert((x, y));
+            }
+        }
+    }
+    blocked
+}
+
+/// Frontier tiles of `player_id` (unowned by them, not blocked in `grid`) with their
+/// Dijkstra cost from `capital`, cheapest first (ties in row-major order). Empty
+/// if `capital` is off the map, where the search would not be bounded by `grid`.
+fn frontier_by_cost(
+    grid: &PathGrid,
+    owner_grid: &[i32],
+    player_id: i32,
+    capital: (i32, i32),
+    map_width: i32,
+    map_height: i32,
+) -> Vec<((i32, i32), f64)> {
+    if grid_index(capital, map_width, map_height).is_none() {
+        return Vec::new();
+    }
+    let owned: Vec<(i32, i32)> = (0..map_height)
+        .flat_map(|y| (0..map_width).map(move |x| (x, y)))
+        .filter(|&pos| {
+            grid_index(pos, map_width, map_height).and_then(|i| owner_grid.get(i))
+                == Some(&player_id)
+        })
+        .collect();
+    let tree = grid.cost_tree(capital, f64::INFINITY, false);
+    let mut ranked: Vec<((i32, i32), f64)> = region_frontier(&owned, map_width, map_height)
+        .into_iter()
+        .filter(|pos| !grid.blocked.contains(pos))
+        .filter_map(|pos| tree.get(&pos).map(|&(cost, _)| (pos, cost)))
+        .collect();
+    ranked.sort_by(|a, b| {
+        a.1.total_cmp(&b.1)
+            .then((a.0 .1, a.0 .0).cmp(&(b.0 .1, b.0 .0)))
+    });
+    ranked
+}
+
+#[allow(clippy::too_many_arguments)]
+fn claimable(
+    owner_grid: &[i32],
+    influence: &[f32],
+    player_id: i32,
+    tile_types: &[i32],
+    impassable_types: &[i32],
+    influence_threshold: f32,
+    map_width: i32,
+    map_height: i32,
+) -> Vec<(i32, i32)> {
+    let owner_at = |pos| grid_index(pos, map_width, map_height).and_then(|i| owner_grid.get(i));
+    let mut tiles = Vec::new();
+    for y in 0..map_height {
+        for x in 0..map_width {
+            let i = (y * map_width + x) as usize;
+            let Some(&owner) = owner_grid.get(i) else {
+                continue;
+            };
+            let net = influence.get(i).copied().unwrap_or(0.0);
+            if owner == player_id
+                || tile_types
+                    .get(i)
+                    .is_some_and(|t| impassable_types.contains(t))
+                || net <= influence_threshold
+                || (owner >= 0 && net <= 0.0)
+            {
+                continue;
+            }
+            if hex_neighbors_vec(x, y)
+                .into_iter()
+                .any(|n| owner_at(n) == Some(&player_id))
+            {
+                tiles.push((x, y));
+            }
+        }
+    }
+    tiles
+}
+
+/// (largest area, its perimeter, number of regions) for `region_stats`.
+fn owned_region_stats(
+    owner_grid: &[i32],
+    player_id: i32,
+    map_width: i32,
+    map_height: i32,
+) -> (i32, i32, i32) {
+    let owned = |i: usize| owner_grid.get(i) == Some(&player_id);
+    let mut seen = vec![false; (map_width.max(0) * map_height.max(0)) as usize];
+    let (mut largest, mut regions) = (Vec::new(), 0);
+    for y in 0..map_height {
+        for x in 0..map_width {
+            let i = (y * map_width + x) as usize;
+            if seen[i] || !owned(i) {
+                continue;
+            }
+            let region = connected_region((x, y), map_width, map_height, owned);
+            for &pos in &region {
+                seen[grid_index(pos, map_width, map_height).unwrap()] = true;
+            }
+            regions += 1;
+            if region.len() > largest.len() {
+                largest = region;
+            }
+        }
+    }
+    let perimeter = largest
+        .iter()
+        .flat_map(|&(x, y)| hex_neighbors_vec(x, y))
+        .filter(|&n| !grid_index(n, map_width, map_height).is_some_and(owned))
+        .count();
+    (largest.len() as i32, perimeter as i32, regions)
+}
+
+/// Count A-B adjacencies by visiting each of A's tiles, so every edge is counted once.
+fn shared_border_edges(
+    owner_grid: &[i32],
+    player_a: i32,
+    player_b: i32,
+    map_width: i32,
+    map_height: i32,
+) -> i32 {
+    let owner_at = |pos| grid_index(pos, map_width, map_height).and_then(|i| owner_grid.get(i));
+    let mut edges = 0;
+    for y in 0..map_height {
+        for x in 0..map_width {
+            if owner_at((x, y)) != Some(&player_a) {
+                continue;
+            }
+            for n in hex_neighbors_vec(x, y) {
+                if owner_at(n) == Some(&player_b) {
+                    edges += 1;
+                }
+            }
+        }
+    }
+    edges
+}
+
+// ============================================================
+// 3. CombatQuery
+// ============================================================
+
+#[derive(GodotClass)]
+#[class(base=RefCounted, init)]
+pub struct CombatQuery;
+
+#[godot_api]
+impl CombatQuery {
+    /// Find all pairs (attacker_idx, target_idx) where units of different owners are within radius.
+    #[func]
+    fn find_targets_in_range(
+        &self,
+        positions: PackedVector2Array,
+        owner_ids: PackedInt32Array,
+        radius: f64,
+    ) -> PackedInt32Array {
+        let pairs = targets_in_range(positions.as_slice(), owner_ids.as_slice(), radius, None);
+        PackedInt32Array::from(pairs.as_slice())
+    }
+
+    /// `find_targets_in_range` under fog: `visible_mask` (row-major, `map_width` wide,
+    /// non-zero = visible) restricts targets to tiles the querying side can see;
+    /// positions are rounded to tiles for the lookup. An empty mask ignores fog.
+    #[func]
+    fn find_targets_in_range_visible(
+        &self,
+        positions: PackedVector2Array,
+        owner_ids: PackedInt32Array,
+        radius: f64,
+        visible_mask: PackedByteArray,
+        map_width: i32,
+    ) -> PackedInt32Array {
+        let mask = (!visible_mask.is_empty()).then(|| (visible_mask.as_slice(), map_width));
+        let pairs = targets_in_range(positions.as_slice(), owner_ids.as_slice(), radius, mask);
+        PackedInt32Array::from(pairs.as_slice())
+    }
+
+    /// `find_targets_in_range` without fog, for very large battles: attackers are
+    /// split across threads when the crate is built with the `parallel` feature
+    /// (serial otherwise). Returns the same pairs in the same order as the serial
+    /// query.
+    #[func]
+    fn find_targets_in_range_parallel(
+        &self,
+        positions: PackedVector2Array,
+        owner_ids: PackedInt32Array,
+        radius: f64,
+    ) -> PackedInt32Array {
+        let pairs = targets_in_range_parallel(positions.as_slice(), owner_ids.as_slice(), radius);
+        PackedInt32Array::from(pairs.as_slice())
+    }
+
+    /// Group `player_id`'s units into clusters (single linkage): units within
+    /// `link_distance` hexes of any member join that cluster.
+    /// `positions` are grid coordinates, rounded to the nearest tile.
+    /// Returns Array of PackedInt32Array of unit indices, ordered by lowest index.
+    #[func]
+    fn cluster_units(
+        &self,
+        positions: PackedVector2Array,
+        owner_ids: PackedInt32Array,
+        player_id: i32,
+        link_distance: i32,
+    ) -> Array<PackedInt32Array> {
+        let n = positions.len().min(owner_ids.len());
+        let tiles: Vec<(i32, i32)> = positions.as_slice()[..n]
+            .iter()
+            .map(|p| (p.x.round() as i32, p.y.round() as i32))
+            .collect();
+        let mut result = Array::new();
+        for cluster in cluster_tiles(&tiles, &owner_ids.as_slice()[..n], player_id, link_distance) {
+            let members: Vec<i32> = cluster.iter().map(|&i| i as i32).collect();
+            result.push(&PackedInt32Array::from(members.as_slice()));
+        }
+        result
+    }
+
+    /// Per-tile count of enemies that could see and shoot each tile: within that
+    /// enemy's `enemy_ranges` entry and with no `blocking_types` tile strictly
+    /// between them (as in `HexLOS.cover_fraction`). `enemy_positions` are grid
+    /// coordinates, rounded to the nearest tile. Returns a row-major width*height grid.
+    #[func]
+    fn exposure_map(
+        &self,
+        enemy_positions: PackedVector2Array,
+        enemy_ranges: PackedInt32Array,
+        tile_types: PackedInt32Array,
+        blocking_types: PackedInt32Array,
+        map_width: i32,
+        map_height: i32,
+    ) -> PackedInt32Array {
+        let enemies: Vec<(i32, i32)> = enemy_positions
+            .as_slice()
+            .iter()
+            .map(|p| (p.x.round() as i32, p.y.round() as i32))
+            .collect();
+        let counts = exposure_counts(
+            &enemies,
+            enemy_ranges.as_slice(),
+            tile_types.as_slice(),
+            blocking_types.as_slice(),
+            map_width,
+            map_height,
+        );
+        PackedInt32Array::from(counts.as_slice())
+    }
+
+    /// Resolve simultaneous moves: each contested tile goes to the claimant with the
+    /// highest priority (ties to the lower index); losers stay on their current tile,
+    /// and a tile held by a unit that stays put cannot be entered.
+    /// Returns the resolved position of every unit, parallel to `current`.
+    #[func]
+    fn resolve_moves(
+        &self,
+        current: PackedVector2Array,
+        desired: PackedVector2Array,
+        priorities: PackedInt32Array,
+    ) -> PackedVector2Array {
+        let n = current.len().min(desired.len());
+        let from = &current.as_slice()[..n];
+        let to = &desired.as_slice()[..n];
+        let tile = |p: &Vector2| (p.x.round() as i32, p.y.round() as i32);
+        let from_tiles: Vec<(i32, i32)> = from.iter().map(tile).collect();
+        let to_tiles: Vec<(i32, i32)> = to.iter().map(tile).collect();
+        let moved = resolve_move_claims(&from_tiles, &to_tiles, priorities.as_slice());
+        let resolved: Vec<Vector2> = (0..n)
+            .map(|i| if moved[i] { to[i] } else { from[i] })
+            .collect();
+        PackedVector2Array::from(resolved.as_slice())
+    }
+
+    /// The tiles of `region` where a unit may spawn: on the map (`map_width` wide, as
+    /// tall as `tile_types`), not of an `impassable_types` type, and holding fewer
+    /// than `max_stack` units in `unit_index` (see `UnitIndex.can_enter`). Keeps
+    /// `region`'s order. Ownership is not checked; pass a region already limited to
+    /// acceptable territory.
+    #[func]
+    fn valid_spawn_tiles(
+        &self,
+        region: Array<Vector2i>,
+        tile_types: PackedInt32Array,
+        impassable_types: PackedInt32Array,
+        unit_index: Gd<UnitIndex>,
+        max_stack: i32,
+        map_width: i32,
+    ) -> Array<Vector2i> {
+        let region: Vec<(i32, i32)> = region.iter_shared().map(|v| (v.x, v.y)).collect();
+        let tiles = spawn_tiles(
+            &region,
+            tile_types.as_slice(),
+            impassable_types.as_slice(),
+            &unit_index.bind(),
+            max_stack,
+            map_width,
+        );
+        path_to_array(Some(tiles))
+    }
+
+    /// Match units to objectives minimizing the total hex distance. Returns, per unit,
+    /// the index of its objective, or -1 when there are more units than objectives.
+    /// Positions are grid coordinates, rounded to the nearest tile. Solved exactly
+    /// (Hungarian algorithm, O(n^2 * m) for n <= m) up to `MAX_EXACT_ASSIGNMENT`
+    /// units or objectives; larger inputs fall back to greedy closest-pair matching.
+    #[func]
+    fn assign_units(
+        &self,
+        unit_positions: PackedVector2Array,
+        objective_positions: PackedVector2Array,
+    ) -> PackedInt32Array {
+        let tile = |p: &Vector2| (p.x.round() as i32, p.y.round() as i32);
+        let units: Vec<(i32, i32)> = unit_positions.as_slice().iter().map(tile).collect();
+        let objectives: Vec<(i32, i32)> = objective_positions.as_slice().iter().map(tile).collect();
+        PackedInt32Array::from(assign_tiles(&units, &objectives).as_slice())
+    }
+}
+
+/// Largest side `assign_units` solves exactly.
+const MAX_EXACT_ASSIGNMENT: usize = 256;
+
+/// Objective index per unit (-1 if unassigned) for `CombatQuery.assign_units`.
+fn assign_tiles(units: &[(i32, i32)], objectives: &[(i32, i32)]) -> Vec<i32> {
+    let mut result = vec![-1; units.len()];
+    if units.is_empty() || objectives.is_empty() {
+        return result;
+    }
+    if units.len().max(objectives.len()) > MAX_EXACT_ASSIGNMENT {
+        let mut pairs: Vec<(i32, usize, usize)> = Vec::new();
+        for (i, &u) in units.iter().enumerate() {
+            for (j, &o) in objectives.iter().enumerate() {
+                pairs.push((hex_distance_xy(u, o), i, j));
+            }
+        }
+        pairs.sort_unstable();
+        let mut taken = vec![false; objectives.len()];
+        for (_, i, j) in pairs {
+            if result[i] < 0 && !taken[j] {
+                result[i] = j as i32;
+                taken[j] = true;
+            }
+        }
+        return result;
+    }
+    // The Hungarian solver needs rows <= columns, so transpose when units outnumber
+    // objectives.
+    if units.len() <= objectives.len() {
+        let cost: Vec<Vec<i64>> = units
+            .iter()
+            .map(|&u| {
+                objectives
+                    .iter()
+                    .map(|&o| hex_distance_xy(u, o) as i64)
+                    .collect()
+            })
+            .collect();
+        for (i, j) in hungarian(&cost).into_iter().enumerate() {
+            result[i] = j as i32;
+        }
+    } else {
+        let cost: Vec<Vec<i64>> = objectives
+            .iter()
+            .map(|&o| {
+                units
+                    .iter()
+                    .map(|&u| hex_distance_xy(u, o) as i64)
+                    .collect()
+            })
+            .collect();
+        for (j, i) in hungarian(&cost).into_iter().enumerate() {
+            result[i] = j as i32;
+        }
+    }
+    result
+}
+
+/// Minimum-cost assignment of every row to a distinct column (rows <= columns),
+/// via shortest augmenting paths with potentials. Returns the column of each row.
+fn hungarian(cost: &[Vec<i64>]) -> Vec<usize> {
+    let n = cost.len();
+    let m = cost[0].len();
+    // 1-based; column 0 is a virtual column holding the row being inserted.
+    let mut u = vec![0i64; n + 1];
+    let mut v = vec![0i64; m + 1];
+    let mut row_of = vec![0usize; m + 1];
+    let mut way = vec![0usize; m + 1];
+    for i in 1..=n {
+        row_of[0] = i;
+        let mut j0 = 0;
+        let mut min_v = vec![i64::MAX; m + 1];
+        let mut used = vec![false; m + 1];
+        loop {
+            used[j0] = true;
+            let i0 = row_of[j0];
+            let mut delta = i64::MAX;
+            let mut j1 = 0;
+            for j in 1..=m {
+                if used[j] {
+                    continue;
+                }
+                let reduced = cost[i0 - 1][j - 1] - u[i0] - v[j];
+                if reduced < min_v[j] {
+                    min_v[j] = reduced;
+                    way[j] = j0;
+                }
+                if min_v[j] < delta {
+                    delta = min_v[j];
+                    j1 = j;
+                }
+            }
+            for j in 0..=m {
+                if used[j] {
+                    u[row_of[j]] += delta;
+                    v[j] -= delta;
+                } else {
+                    min_v[j] -= delta;
+                }
+            }
+            j0 = j1;
+            if row_of[j0] == 0 {
+                break;
+            }
+        }
+        // Flip the augmenting path back to the virtual column.
+        while j0 != 0 {
+            let j1 = way[j0];
+            row_of[j0] = row_of[j1];
+            j0 = j1;
+        }
+    }
+    let mut col_of = vec![0; n];
+    for j in 1..=m {
+        if row_of[j] != 0 {
+            col_of[row_of[j] - 1] = j - 1;
+        }
+    }
+    col_of
+}
+
+/// Row-major count of `enemies` in range of each tile with an unobstructed line to it.
+fn exposure_counts(
+    enemies: &[(i32, i32)],
+    ranges: &[i32],
+    tile_types: &[i32],
+    blocking_types: &[i32],
+    map_width: i32,
+    map_height: i32,
+) -> Vec<i32> {
+    let mut counts = vec![0; (map_width.max(0) * map_height.max(0)) as usize];
+    for (&enemy, &range) in enemies.iter().zip(ranges) {
+        let from = Vector2i::new(enemy.0, enemy.1);
+        for pos in hex_disk(enemy, range) {
+            let Some(i) = grid_index(pos, map_width, map_height) else {
+                continue;
+            };
+            let to = Vector2i::new(pos.0, pos.1);
+            if line_cover(from, to, tile_types, blocking_types, map_width, map_height) == 0.0 {
+                counts[i] += 1;
+            }
+        }
+    }
+    counts
+}
+
+/// Which units get to move. Units that stay (by choice or after losing a claim)
+/// hold their current tile, which can bump other claimants, so resolve until stable.
+fn resolve_move_claims(from: &[(i32, i32)], to: &[(i32, i32)], priorities: &[i32]) -> Vec<bool> {
+    let n = from.len().min(to.len());
+    let priority = |i: usize| priorities.get(i).copied().unwrap_or(0);
+    let mut moving: Vec<bool> = (0..n).map(|i| from[i] != to[i]).collect();
+    loop {
+        let held: HashSet<(i32, i32)> = (0..n).filter(|&i| !moving[i]).map(|i| from[i]).collect();
+        let mut winners: HashMap<(i32, i32), usize> = HashMap::new();
+        for i in (0..n).filter(|&i| moving[i]) {
+            winners
+                .entry(to[i])
+                .and_modify(|w| {
+                    if priority(i) > priority(*w) {
+                        *w = i;
+                    }
+                })
+                .or_insert(i);
+        }
+        let mut changed = false;
+        for i in 0..n {
+            if moving[i] && (held.contains(&to[i]) || winners[&to[i]] != i) {
+                moving[i] = false;
+                changed = true;
+            }
+        }
+        if !changed {
+            return moving;
+        }
+    }
+}
+
+/// `CombatQuery.valid_spawn_tiles` on plain slices.
+fn spawn_tiles(
+    region: &[(i32, i32)],
+    tile_types: &[i32],
+    impassable_types: &[i32],
+    units: &UnitIndex,
+    max_stack: i32,
+    map_width: i32,
+) -> Vec<(i32, i32)> {
+    let rows = grid_rows(tile_types.len(), map_width);
+    region
+        .iter()
+        .copied()
+        .filter(|&tile| {
+            let passable = grid_index(tile, map_width, rows)
+                .and_then(|i| tile_types.get(i))
+                .is_some_and(|t| !impassable_types.contains(t));
+            passable && units.can_enter(Vector2i::new(tile.0, tile.1), max_stack)
+        })
+        .collect()
+}
+
+/// Flattened (attacker, target) index pairs of enemy units within `radius`.
+/// With a mask, targets standing on tiles that are not visible are skipped.
+fn targets_in_range(
+    pos: &[Vector2],
+    owners: &[i32],
+    radius: f64,
+    visible_mask: Option<(&[u8], i32)>,
+) -> Vec<i32> {
+    let r2 = (radius * radius) as f32;
+    let n = pos.len().min(owners.len());
+    let visible: Vec<bool> = pos[..n]
+        .iter()
+        .map(|p| match visible_mask {
+            None => true,
+            Some((mask, w)) => {
+                let (x, y) = (p.x.round() as i32, p.y.round() as i32);
+                x >= 0 && y >= 0 && x < w && mask.get((y * w + x) as usize).is_some_and(|&v| v != 0)
+            }
+        })
+        .collect();
+
+    // Simple O(n^2) — fine for <200 units on 50x50 map
+    (0..n)
+        .flat_map(|i| attacker_pairs(pos, owners, &visible, r2, i))
+        .collect()
+}
+
+/// `targets_in_range` without fog, with attackers split across threads when the
+/// `parallel` feature is enabled. Pairs come out in the same order as the serial
+/// version either way.
+#[cfg(feature = "parallel")]
+fn targets_in_range_parallel(pos: &[Vector2], owners: &[i32], radius: f64) -> Vec<i32> {
+    use rayon::prelude::*;
+    let r2 = (radius * radius) as f32;
+    let n = pos.len().min(owners.len());
+    let visible = vec![true; n];
+    (0..n)
+        .into_par_iter()
+        .flat_map_iter(|i| attacker_pairs(pos, owners, &visible, r2, i))
+        .collect()
+}
+
+#[cfg(not(feature = "parallel"))]
+fn targets_in_range_parallel(pos: &[Vector2], owners: &[i32], radius: f64) -> Vec<i32> {
+    targets_in_range(pos, owners, radius, None)
+}
+
+/// Flattened (attacker, target) pairs for attacker `i` against every visible enemy
+/// within squared distance `r2`.
+fn attacker_pairs(
+    pos: &[Vector2],
+    owners: &[i32],
+    visible: &[bool],
+    r2: f32,
+    i: usize,
+) -> Vec<i32> {
+    let mut result = Vec::new();
+    for j in 0..visible.len() {
+        if i == j {
+            continue;
+        }
+        if owners[i] == owners[j] || !visible[j] {
+            continue;
+        }
+        let dx = pos[i].x - pos[j].x;
+        let dy = pos[i].y - pos[j].y;
+        if dx * dx + dy * dy <= r2 {
+            result.push(i as i32);
+            result.push(j as i32);
+        }
+    }
+    result
+}
+
+/// Single-linkage clusters of the units owned by `player_id`, as sorted index lists.
+fn cluster_tiles(
+    tiles: &[(i32, i32)],
+    owners: &[i32],
+    player_id: i32,
+    link_distance: i32,
+) -> Vec<Vec<usize>> {
+    let members: Vec<usize> = (0..tiles.len().min(owners.len()))
+        .filter(|&i| owners[i] == player_id)
+        .collect();
+    let mut assigned = vec![false; tiles.len()];
+    let mut clusters = Vec::new();
+    for &seed in &members {
+        if assigned[seed] {
+            continue;
+        }
+        assigned[seed] = true;
+        let mut cluster = vec![seed];
+        let mut next = 0;
+        while next < cluster.len() {
+            let cur = cluster[next];
+            next += 1;
+            for &other in &members {
+                if !assigned[other] && hex_distance_xy(tiles[cur], tiles[other]) <= link_distance {
+                    assigned[other] = true;
+                    cluster.push(other);
+                }
+            }
+        }
+        cluster.sort_unstable();
+        clusters.push(cluster);
+    }
+    clusters
+}
+
+// ============================================================
+// 4. ResourceCounter
+// ============================================================
+
+#[derive(GodotClass)]
+#[class(base=RefCounted, init)]
+pub struct ResourceCounter {
+    // Cached state for incremental updates (`set_base` / `apply_change`)
+    tile_types: Vec<i32>,
+    owner_grid: Vec<i32>,
+    totals: Vec<[i32; 3]>,
+}
+
+#[godot_api]
+impl ResourceCounter {
+    /// Returns Dictionary { player_id -> PackedInt32Array [food, production, gold] }
+    #[func]
+    fn compute_resources(
+        &self,
+        tile_types: PackedInt32Array,
+        owner_grid: PackedInt32Array,
+        num_players: i32,
+    ) -> Dictionary<Variant, Variant> {
+        let np = num_players.max(0) as usize;
+        totals_to_dict(&resource_totals(
+            tile_types.as_slice(),
+            owner_grid.as_slice(),
+            np,
+        ))
+    }
+
+    /// Cache `tile_types`, `owner_grid` and the resulting per-player totals so later
+    /// ownership changes can be applied with `apply_change` instead of a full recompute.
+    #[func]
+    fn set_base(
+        &mut self,
+        tile_types: PackedInt32Array,
+        owner_grid: PackedInt32Array,
+        num_players: i32,
+    ) {
+        self.tile_types = tile_types.to_vec();
+        self.owner_grid = owner_grid.to_vec();
+        self.totals = resource_totals(
+            &self.tile_types,
+            &self.owner_grid,
+            num_players.max(0) as usize,
+        );
+    }
+
+    /// Move tile `tile_index` to `new_owner` (-1 = unowned) in the cached state from
+    /// `set_base`, moving its yield between the two owners' totals.
+    /// Returns the updated totals in the same format as `compute_resources`.
+    #[func]
+    fn apply_change(&mut self, tile_index: i32, new_owner: i32) -> Dictionary<Variant, Variant> {
+        self.change_owner(tile_index, new_owner);
+        totals_to_dict(&self.totals)
+    }
+
+    /// Like `compute_resources`, but grouped by the city working each tile.
+    /// `city_grid[i]` is the working city id or -1 for none.
+    /// Returns Dictionary { city_id -> PackedInt32Array [food, production, gold] }
+    #[func]
+    fn compute_resources_by_city(
+        &self,
+        tile_types: PackedInt32Array,
+        city_grid: PackedInt32Array,
+        num_cities: i32,
+    ) -> Dictionary<Variant, Variant> {
+        let nc = num_cities.max(0) as usize;
+        totals_to_dict(&resource_totals(
+            tile_types.as_slice(),
+            city_grid.as_slice(),
+            nc,
+        ))
+    }
+
+    /// Batched `compute_resources` over several owner grids sharing one `tile_types`.
+    /// Returns Array of Dictionary, one per entry of `owner_grids`, in order.
+    #[func]
+    fn compute_resources_batch(
+        &self,
+        tile_types: PackedInt32Array,
+        owner_grids: Array<PackedInt32Array>,
+        num_players: i32,
+    ) -> Array<Variant> {
+        let grids: Vec<PackedInt32Array> = owner_grids.iter_shared().collect();
+        let batch = resource_totals_batch(
+            tile_types.as_slice(),
+            grids.iter().map(|g| g.as_slice()),
+            num_players.max(0) as usize,
+        );
+        let mut result = Array::new();
+        for totals in &batch {
+            result.push(&Variant::from(totals_to_dict(totals)));
+        }
+        result
+    }
+
+    /// Yield of every tile, owned or not, as
+    /// Dictionary { "food": PackedInt32Array, "production": ..., "gold": ... }
+    /// parallel to `tile_types`.
+    #[func]
+    fn per_tile_yields(&self, tile_types: PackedInt32Array) -> Dictionary<Variant, Variant> {
+        let [food, production, gold] = per_tile_yield_grids(tile_types.as_slice());
+        let mut dict = Dictionary::new();
+        for (key, grid) in [("food", food), ("production", production), ("gold", gold)] {
+            dict.set(
+                &Variant::from(key),
+                &Variant::from(PackedInt32Array::from(grid.as_slice())),
+            );
+        }
+        dict
+    }
+
+    /// Bonus yields granted by neighboring tiles. `bonus_rules` maps a source tile type
+    /// to `[food, production, gold]` added to each of its in-bounds neighbors.
+    /// Returns Dictionary { "food": PackedInt32Array, "production": ..., "gold": ... }
+    /// parallel to `tile_types`.
+    #[func]
+    fn adjacency_bonuses(
+        &self,
+        tile_types: PackedInt32Array,
+        bonus_rules: Dictionary<Variant, Variant>,
+        map_width: i32,
+        map_height: i32,
+    ) -> Dictionary<Variant, Variant> {
+        let mut rules = HashMap::new();
+        for (key, value) in bonus_rules.iter_shared() {
+            let Ok(tile_type) = key.try_to::<i32>() else {
+                continue;
+            };
+            let bonus: Vec<i32> = match value.try_to::<PackedInt32Array>() {
+                Ok(packed) => packed.to_vec(),
+                Err(_) => match value.try_to::<Array<Variant>>() {
+                    Ok(arr) => arr
+                        .iter_shared()
+                        .map(|v| v.try_to::<i32>().unwrap_or(0))
+                        .collect(),
+                    Err(_) => continue,
+                },
+            };
+            let at = |i: usize| bonus.get(i).copied().unwrap_or(0);
+            rules.insert(tile_type, [at(0), at(1), at(2)]);
+        }
+        let [food, production, gold] =
+            adjacency_bonus_grids(tile_types.as_slice(), &rules, map_width, map_height);
+        let mut dict = Dictionary::new();
+        for (key, grid) in [("food", food), ("production", production), ("gold", gold)] {
+            dict.set(
+                &Variant::from(key),
+                &Variant::from(PackedInt32Array::from(grid.as_slice())),
+            );
+        }
+        dict
+    }
+}
+
+impl ResourceCounter {
+    fn change_owner(&mut self, tile_index: i32, new_owner: i32) {
+        let Some(i) = usize::try_from(tile_index)
+            .ok()
+            .filter(|&i| i < self.owner_grid.len())
+        else {
+            return;
+        };
+        let (f, p, g) = tile_yield(self.tile_types.get(i).copied().unwrap_or(-1));
+        let old_owner = std::mem::replace(&mut self.owner_grid[i], new_owner);
+        if let Some(t) = usize::try_from(old_owner)
+            .ok()
+            .and_then(|o| self.totals.get_mut(o))
+        {
+            t[0] -= f;
+            t[1] -= p;
+            t[2] -= g;
+        }
+        if let Some(t) = usize::try_from(new_owner)
+            .ok()
+            .and_then(|o| self.totals.get_mut(o))
+        {
+            t[0] += f;
+            t[1] += p;
+            t[2] += g;
+        }
+    }
+}
+
+/// Food, production and gold bonus grids: every tile whose type has a rule adds that
+/// rule's bonus to each in-bounds neighbor.
+fn adjacency_bonus_grids(
+    tile_types: &[i32],
+    rules: &HashMap<i32, [i32; 3]>,
+    map_width: i32,
+    map_height: i32,
+) -> [Vec<i32>; 3] {
+    let n = tile_types.len();
+    let mut grids = [vec![0; n], vec![0; n], vec![0; n]];
+    for y in 0..map_height {
+        for x in 0..map_width {
+            let Some(bonus) = grid_index((x, y), map_width, map_height)
+                .and_then(|i| tile_types.get(i))
+                .and_then(|t| rules.get(t))
+            else {
+                continue;
+            };
+            for nb in hex_neighbors_vec(x, y) {
+                if let Some(ni) = grid_index(nb, map_width, map_height).filter(|&ni| ni < n) {
+                    for (grid, b) in grids.iter_mut().zip(bonus) {
+                        grid[ni] += b;
+                    }
+                }
+            }
+        }
+    }
+    grids
+}
+
+/// Food, production and gold grids with the yield of each tile.
+fn per_tile_yield_grids(tile_types: &[i32]) -> [Vec<i32>; 3] {
+    let mut grids = [
+        Vec::with_capacity(tile_types.len()),
+        Vec::with_capacity(tile_types.len()),
+        Vec::with_capacity(tile_types.len()),
+    ];
+    for &tile_type in tile_types {
+        let (f, p, g) = tile_yield(tile_type);
+        grids[0].push(f);
+        grids[1].push(p);
+        grids[2].push(g);
+    }
+    grids
+}
+
+/// Yield of a tile type as (food, production, gold).
+fn tile_yield(tile_type: i32) -> (i32, i32, i32) {
+    match tile_type {
+        0 => (1, 1, 0), // plains
+        1 => (0, 2, 0), // forest
+        2 => (0, 3, 1), // mountain
+        3 => (0, 0, 2), // water
+        4 => (1, 0, 1), // desert
+        5 => (3, 1, 0), // plains_fertile
+        _ => (0, 0, 0),
+    }
+}
+
+/// Per-owner [food, production, gold] totals over owned tiles. Owners are
+/// player ids or city ids depending on the grid passed in.
+fn resource_totals(tile_types: &[i32], owner_grid: &[i32], num_players: usize) -> Vec<[i32; 3]> {
+    let mut totals = vec![[0i32; 3]; num_players];
+    for (&tile_type, &owner) in tile_types.iter().zip(owner_grid) {
+        if owner < 0 || owner as usize >= num_players {
+            continue;
+        }
+        let (f, p, g) = tile_yield(tile_type);
+        let pid = owner as usize;
+        totals[pid][0] += f;
+        totals[pid][1] += p;
+        totals[pid][2] += g;
+    }
+    totals
+}
+
+/// `resource_totals` for each owner grid in turn, all over the same `tile_types`.
+fn resource_totals_batch<'a>(
+    tile_types: &[i32],
+    owner_grids: impl IntoIterator<Item = &'a [i32]>,
+    num_players: usize,
+) -> Vec<Vec<[i32; 3]>> {
+    owner_grids
+        .into_iter()
+        .map(|grid| resource_totals(tile_types, grid, num_players))
+        .collect()
+}
+
+/// Dictionary { index -> PackedInt32Array [food, production, gold] }.
+fn totals_to_dict(totals: &[[i32; 3]]) -> Dictionary<Variant, Variant> {
+    let mut dict = Dictionary::new();
+    for (id, t) in totals.iter().enumerate() {
+        let mut arr = PackedInt32Array::new();
+        arr.push(t[0]);
+        arr.push(t[1]);
+        arr.push(t[2]);
+        let k = Variant::from(id as i32);
+        let v = Variant::from(arr);
+        dict.set(&k, &v);
+    }
+    dict
+}
+
+// ============================================================
+// 5. HexLOS
+// ============================================================
+
+#[derive(GodotClass)]
+#[class(base=RefCounted, init)]
+pub struct HexLOS;
+
+#[godot_api]
+impl HexLOS {
+    /// Line-of-sight check: returns true if no mountain (type=2) blocks the line from→to.
+    /// Uses cube-coordinate lerp to walk hex tiles along the line.
+    #[func]
+    fn has_line_of_sight(
+        &self,
+        from: Vector2i,
+        to: Vector2i,
+        tile_types: PackedInt32Array,
+        map_width: i32,
+        map_height: i32,
+    ) -> bool {
+        line_of_sight(from, to, tile_types.as_slice(), map_width, map_height)
+    }
+
+    /// Fraction of the intermediate tiles on the line from→to whose type is in
+    /// `blocking_types` (0.0 = clear, 1.0 = fully blocked), for to-hit penalties.
+    /// Out-of-bounds tiles count as blocking. Adjacent or equal tiles return 0.0.
+    #[func]
+    fn cover_fraction(
+        &self,
+        from: Vector2i,
+        to: Vector2i,
+        tile_types: PackedInt32Array,
+        blocking_types: PackedInt32Array,
+        map_width: i32,
+        map_height: i32,
+    ) -> f32 {
+        line_cover(
+            from,
+            to,
+            tile_types.as_slice(),
+            blocking_types.as_slice(),
+            map_width,
+            map_height,
+        )
+    }
+
+    /// Where a projectile fired from `from` at `to` lands: the last tile on the line
+    /// before the first one whose type is in `blocking_types` or that is off the
+    /// map, or `to` if nothing is in the way. A blocker on `to` itself stops the
+    /// projectile too. Returns `from` if the very first step is blocked.
+    #[func]
+    fn impact_tile(
+        &self,
+        from: Vector2i,
+        to: Vector2i,
+        tile_types: PackedInt32Array,
+        blocking_types: PackedInt32Array,
+        map_width: i32,
+        map_height: i32,
+    ) -> Vector2i {
+        let (x, y) = line_impact(
+            from,
+            to,
+            tile_types.as_slice(),
+            blocking_types.as_slice(),
+            map_width,
+            map_height,
+        );
+        Vector2i::new(x, y)
+    }
+}
+
+/// `has_line_of_sight` on plain slices.
+fn line_of_sight(
+    from: Vector2i,
+    to: Vector2i,
+    tile_types: &[i32],
+    map_width: i32,
+    map_height: i32,
+) -> bool {
+    let w = map_width as usize;
+    for (col, row) in line_interior(from, to) {
+        if col < 0 || row < 0 || col >= map_width || row >= map_height {
+            return false; // out of bounds blocks LOS
+        }
+        let idx = row as usize * w + col as usize;
+        if idx < tile_types.len() && tile_types[idx] == 2 {
+            return false; // mountain blocks
+        }
+    }
+    true
+}
+
+/// Nudge added to the start of a cube lerp so points landing exactly between two
+/// hexes always round the same way. The components sum to zero to stay on the
+/// x + y + z = 0 plane.
+const LINE_NUDGE: (f64, f64, f64) = (1e-6, 2e-6, -3e-6);
+
+/// Odd-q offset tiles on the line from `from` to `to`, both endpoints included,
+/// walked by cube-coordinate lerp. The walk always runs from the lexicographically
+/// smaller endpoint (reversed afterwards if needed), so `to -> from` visits exactly
+/// the tiles of `from -> to` in reverse.
+fn hex_line_xy(from: Vector2i, to: Vector2i) -> Vec<(i32, i32)> {
+    let flip = (to.x, to.y) < (from.x, from.y);
+    let (a, b) = if flip { (to, from) } else { (from, to) };
+    let dist = HexMath::hex_distance(a, b);
+    let mut line: Vec<(i32, i32)> = (0..=dist)
+        .map(|step| {
+            let t = if dist == 0 {
+                0.0
+            } else {
+                step as f64 / dist as f64
+            };
+            lerp_tile(a, b, t)
+        })
+        .collect();
+    if flip {
+        line.reverse();
+    }
+    line
+}
+
+/// Offset tile at fraction `t` of the way from `a` to `b`: lerp in cube space
+/// (starting from `a` nudged by `LINE_NUDGE`) and round with `cube_round`.
+fn lerp_tile(a: Vector2i, b: Vector2i, t: f64) -> (i32, i32) {
+    // Convert to cube coords
+    let (ax, ay) = to_axial(a);
+    let (bx, by) = to_axial(b);
+    let (ax, ay, az) = (
+        ax as f64 + LINE_NUDGE.0,
+        ay as f64 + LINE_NUDGE.1,
+        (-ax - ay) as f64 + LINE_NUDGE.2,
+    );
+    let (bx, by, bz) = (bx as f64, by as f64, (-bx - by) as f64);
+
+    // Lerp in cube space
+    let fx = ax + (bx - ax) * t;
+    let fy = ay + (by - ay) * t;
+    let fz = az + (bz - az) * t;
+
+    // Round to nearest cube hex, then back to odd-q offset
+    let (rx, ry, _rz) = cube_round(fx, fy, fz);
+    from_axial(rx, ry)
+}
+
+/// Tiles of `hex_line_xy` strictly between `from` and `to`.
+fn line_interior(from: Vector2i, to: Vector2i) -> Vec<(i32, i32)> {
+    let line = hex_line_xy(from, to);
+    if line.len() <= 2 {
+        return Vec::new();
+    }
+    line[1..line.len() - 1].to_vec()
+}
+
+fn line_cover(
+    from: Vector2i,
+    to: Vector2i,
+    tile_types: &[i32],
+    blocking_types: &[i32],
+    map_width: i32,
+    map_height: i32,
+) -> f32 {
+    let interior = line_interior(from, to);
+    if interior.is_empty() {
+        return 0.0;
+    }
+    let blocking = interior
+        .iter()
+        .filter(|&&pos| {
+            grid_index(pos, map_width, map_height).is_none_or(|i| {
+                tile_types
+                    .get(i)
+                    .is_some_and(|t| blocking_types.contains(t))
+            })
+        })
+        .count();
+    blocking as f32 / interior.len() as f32
+}
+
+fn visible_tiles(
+    from: (i32, i32),
+    sight_range: i32,
+    tile_types: &[i32],
+    blocking_types: &[i32],
+    map_width: i32,
+    map_height: i32,
+) -> Vec<(i32, i32)> {
+    if grid_index(from, map_width, map_height).is_none() {
+        return Vec::new();
+    }
+    let opaque = |pos: (i32, i32)| {
+        grid_index(pos, map_width, map_height).is_none_or(|i| {
+            tile_types
+                .get(i)
+                .is_some_and(|t| blocking_types.contains(t))
+        })
+    };
+    let origin = Vector2i::new(from.0, from.1);
+    let (cq, cr) = to_axial(origin);
+    let radius = sight_range.max(0);
+    (0..1 + 3 * radius * (radius + 1))
+        .map(|i| {
+            let (dq, dr) = spiral_axial(i);
+            from_axial(cq + dq, cr + dr)
+        })
+        .filter(|&pos| {
+            grid_index(pos, map_width, map_height).is_some()
+                && !line_interior(origin, Vector2i::new(pos.0, pos.1))
+                    .into_iter()
+                    .any(opaque)
+        })
+        .collect()
+}
+
+fn line_impact(
+    from: Vector2i,
+    to: Vector2i,
+    tile_types: &[i32],
+    blocking_types: &[i32],
+    map_width: i32,
+    map_height: i32,
+) -> (i32, i32) {
+    let line = hex_line_xy(from, to);
+    let mut last = line[0];
+    for &pos in &line[1..] {
+        let blocks = grid_index(pos, map_width, map_height).is_none_or(|i| {
+            tile_types
+                .get(i)
+                .is_some_and(|t| blocking_types.contains(t))
+        });
+        if blocks {
+            break;
+        }
+        last = pos;
+    }
+    last
+}
+
+/// Round fractional cube coordinates to the nearest hex by recomputing the
+/// component with the largest rounding error. On exact ties the order of
+/// preference is deterministic (z, then y, then x is recomputed); callers that
+/// lerp between tiles should still apply `LINE_NUDGE` so ties do not occur.
+fn cube_round(x: f64, y: f64, z: f64) -> (i32, i32, i32) {
+    let mut rx = x.round();
+    let mut ry = y.round();
+    let mut rz = z.round();
+
+    let dx = (rx - x).abs();
+    let dy = (ry - y).abs();
+    let dz = (rz - z).abs();
+
+    if dx > dy && dx > dz {
+        rx = -ry - rz;
+    } else if dy > dz {
+        ry = -rx - rz;
+    } else {
+        rz = -rx - ry;
+    }
+    let _ = rz;
+    (rx as i32, ry as i32, rz as i32)
+}
+
+// ============================================================
+// 6. MapEditor
+// ============================================================
+
+#[derive(GodotClass)]
+#[class(base=RefCounted, init)]
+pub struct MapEditor;
+
+#[godot_api]
+impl MapEditor {
+    /// Paint-bucket fill: returns a copy of `tile_types` where the hex-connected region
+    /// of tiles sharing `start`'s type is replaced with `new_type`.
+    /// Returns the grid unchanged if `start` is out of bounds or already `new_type`.
+    #[func]
+    fn flood_fill(
+        &self,
+        tile_types: PackedInt32Array,
+        start: Vector2i,
+        new_type: i32,
+        map_width: i32,
+        map_height: i32,
+    ) -> PackedInt32Array {
+        let filled = flood_fill_grid(
+            tile_types.as_slice(),
+            (start.x, start.y),
+            new_type,
+            map_width,
+            map_height,
+        );
+        PackedInt32Array::from(filled.as_slice())
+    }
+
+    /// Hex distance from every tile to the nearest `blocked` tile (0 on blocked tiles),
+    /// as a row-major width*height grid; -1 everywhere if no blocked tile is on the
+    /// map. Open ground scores high, tiles hugging obstacles low.
+    #[func]
+    fn obstacle_distance_field(
+        &self,
+        blocked: Array<Vector2i>,
+        map_width: i32,
+        map_height: i32,
+    ) -> PackedInt32Array {
+        let obstacles: Vec<(i32, i32)> = blocked.iter_shared().map(|v| (v.x, v.y)).collect();
+        let dist = obstacle_distances(&obstacles, map_width, map_height);
+        PackedInt32Array::from(dist.as_slice())
+    }
+
+    /// Estimated diameter (longest shortest path, in steps) of the passable region
+    /// containing `start`, by double BFS: the farthest tile A from `start`, then the
+    /// farthest distance from A. Exact on tree-like regions, a lower bound in general.
+    /// -1 if `start` is off the map or blocked.
+    #[func]
+    fn region_diameter(
+        &self,
+        blocked: Array<Vector2i>,
+        start: Vector2i,
+        map_width: i32,
+        map_height: i32,
+    ) -> i32 {
+        let blocked: HashSet<(i32, i32)> = blocked.iter_shared().map(|v| (v.x, v.y)).collect();
+        region_diameter_estimate((start.x, start.y), &blocked, map_width, map_height)
+    }
+
+    /// Grid `factor` times wider and taller: fine tile (x, y) takes the value of coarse
+    /// tile (x / factor, y / factor), so each coarse tile fills a `factor` x `factor`
+    /// block. A `factor` below 1 is treated as 1. Missing source cells read as -1.
+    #[func]
+    fn upscale_grid(
+        &self,
+        grid: PackedInt32Array,
+        src_width: i32,
+        src_height: i32,
+        factor: i32,
+    ) -> PackedInt32Array {
+        let fine = upscale_values(grid.as_slice(), src_width, src_height, factor);
+        PackedInt32Array::from(fine.as_slice())
+    }
+
+    /// Inverse of `upscale_grid`: each `factor` x `factor` block of the
+    /// `src_width` x `src_height` grid becomes one tile holding the block's most
+    /// common value (ties to the smaller value). Partial blocks at the right and
+    /// bottom edges vote with the tiles they have.
+    #[func]
+    fn downscale_grid(
+        &self,
+        grid: PackedInt32Array,
+        src_width: i32,
+        src_height: i32,
+        factor: i32,
+    ) -> PackedInt32Array {
+        let coarse = downscale_values(grid.as_slice(), src_width, src_height, factor);
+        PackedInt32Array::from(coarse.as_slice())
+    }
+
+    /// Connected bodies of water: a row-major grid where each `water_type` tile holds
+    /// the id of its hex-connected water body (0, 1, ... in row-major order of each
+    /// body's first tile) and every other tile holds -1.
+    #[func]
+    fn label_water_bodies(
+        &self,
+        tile_types: PackedInt32Array,
+        water_type: i32,
+        map_width: i32,
+        map_height: i32,
+    ) -> PackedInt32Array {
+        let labels = label_components(map_width, map_height, |i| {
+            tile_types.as_slice().get(i) == Some(&water_type)
+        });
+        PackedInt32Array::from(labels.as_slice())
+    }
+
+    /// Composite an override layer onto `base`: each cell takes `overlay`'s value
+    /// unless that equals `overlay_sentinel` ("no override"). The grids must have
+    /// the same length; otherwise an error is logged and `base` is returned as is.
+    #[func]
+    fn overlay_grids(
+        &self,
+        base: PackedInt32Array,
+        overlay: PackedInt32Array,
+        overlay_sentinel: i32,
+    ) -> PackedInt32Array {
+        match overlay_values(base.as_slice(), overlay.as_slice(), overlay_sentinel) {
+            Some(merged) => PackedInt32Array::from(merged.as_slice()),
+            None => {
+                godot_error!(
+                    "overlay_grids: base has {} cells but overlay has {}",
+                    base.len(),
+                    overlay.len()
+                );
+                base
+            }
+        }
+    }
+
+    /// Stable 64-bit FNV-1a hash of both grids, for comparing map state between
+    /// multiplayer clients to detect desyncs. Depends only on the values (hashed as
+    /// little-endian bytes, each grid prefixed with its length), so it is identical
+    /// across platforms and runs.
+    #[func]
+    fn fingerprint(&self, tile_types: PackedInt32Array, owner_grid: PackedInt32Array) -> i64 {
+        grid_fingerprint(&[tile_types.as_slice(), owner_grid.as_slice()]) as i64
+    }
+}
+
+fn upscale_values(grid: &[i32], src_width: i32, src_height: i32, factor: i32) -> Vec<i32> {
+    let f = factor.max(1);
+    let (w, h) = (src_width.max(0) * f, src_height.max(0) * f);
+    (0..w * h)
+        .map(|i| {
+            let (x, y) = (i % w / f, i / w / f);
+            grid.get((y * src_width + x) as usize)
+                .copied()
+                .unwrap_or(-1)
+        })
+        .collect()
+}
+
+fn downscale_values(grid: &[i32], src_width: i32, src_height: i32, factor: i32) -> Vec<i32> {
+    let f = factor.max(1);
+    let (w, h) = (src_width.max(0), src_height.max(0));
+    let (cw, ch) = ((w + f - 1) / f, (h + f - 1) / f);
+    let mut result = Vec::with_capacity((cw * ch) as usize);
+    for cy in 0..ch {
+        for cx in 0..cw {
+            let mut votes: HashMap<i32, i32> = HashMap::new();
+            for y in cy * f..((cy + 1) * f).min(h) {
+                for x in cx * f..((cx + 1) * f).min(w) {
+                    let v = grid.get((y * w + x) as usize).copied().unwrap_or(-1);
+                    *votes.entry(v).or_default() += 1;
+                }
+            }
+            let winner = votes
+                .into_iter()
+                .max_by_key(|&(value, count)| (count, std::cmp::Reverse(value)))
+                .map_or(-1, |(value, _)| value);
+            result.push(winner);
+        }
+    }
+    result
+}
+
+/// `overlay` composited onto `base`, or `None` if their lengths differ.
+fn overlay_values(base: &[i32], overlay: &[i32], sentinel: i32) -> Option<Vec<i32>> {
+    (base.len() == overlay.len()).then(|| {
+        base.iter()
+            .zip(overlay)
+            .map(|(&b, &o)| if o == sentinel { b } else { o })
+            .collect()
+    })
+}
+
+fn grid_fingerprint(grids: &[&[i32]]) -> u64 {
+    const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+    const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+    let mut hash = FNV_OFFSET;
+    let mut feed = |bytes: [u8; 4]| {
+        for b in bytes {
+            hash ^= b as u64;
+            hash = hash.wrapping_mul(FNV_PRIME);
+        }
+    };
+    for grid in grids {
+        feed((grid.len() as u32).to_le_bytes());
+        for &v in *grid {
+            feed(v.to_le_bytes());
+        }
+    }
+    hash
+}
+
+/// Tiles hex-connected to `start` (inclusive) for which `matches(index)` holds, in BFS order.
+fn connected_region(
+    start: (i32, i32),
+    map_width: i32,
+    map_height: i32,
+    matches: impl Fn(usize) -> bool,
+) -> Vec<(i32, i32)> {
+    let Some(si) = grid_index(start, map_width, map_height) else {
+        return Vec::new();
+    };
+    if !matches(si) {
+        return Vec::new();
+    }
+    let mut seen = vec![false; (map_width * map_height) as usize];
+    seen[si] = true;
+    let mut region = vec![start];
+    let mut next = 0;
+    while next < region.len() {
+        let (x, y) = region[next];
+        next += 1;
+        for n in hex_neighbors_vec(x, y) {
+            if let Some(ni) = grid_index(n, map_width, map_height) {
+                if !seen[ni] && matches(ni) {
+                    seen[ni] = true;
+                    region.push(n);
+                }
+            }
+        }
+    }
+    region
+}
+
+/// Component id per tile for the tiles accepted by `matches` (-1 elsewhere), ids
+/// assigned in row-major order of each component's first tile.
+fn label_components(map_width: i32, map_height: i32, matches: impl Fn(usize) -> bool) -> Vec<i32> {
+    let mut labels = vec![-1; (map_width.max(0) * map_height.max(0)) as usize];
+    let mut next_label = 0;
+    for i in 0..labels.len() {
+        if labels[i] >= 0 || !matches(i) {
+            continue;
+        }
+        let start = ((i as i32) % map_width, (i as i32) / map_width);
+        for pos in connected_region(start, map_width, map_height, &matches) {
+            labels[grid_index(pos, map_width, map_height).unwrap()] = next_label;
+        }
+        next_label += 1;
+    }
+    labels
+}
+
+fn flood_fill_grid(
+    tile_types: &[i32],
+    start: (i32, i32),
+    new_type: i32,
+    map_width: i32,
+    map_height: i32,
+) -> Vec<i32> {
+    let mut result = tile_types.to_vec();
+    let Some(old_type) = grid_index(start, map_width, map_height).and_then(|i| tile_types.get(i))
+    else {
+        return result;
+    };
+    if *old_type == new_type {
+        return result;
+    }
+    let region = connected_region(start, map_width, map_height, |i| {
+        tile_types.get(i) == Some(old_type)
+    });
+    for pos in region {
+        if let Some(i) = grid_index(pos, map_width, map_height) {
+            result[i] = new_type;
+        }
+    }
+    result
+}
+
+// ============================================================
+// 7. HexBitset
+// ============================================================
+
+/// Compact boolean tile set, one bit per tile, row-major.
+#[derive(GodotClass)]
+#[class(base=RefCounted, init)]
+pub struct HexBitset {
+    bits: Vec<u64>,
+    width: i32,
+    height: i32,
+}
+
+#[godot_api]
+impl HexBitset {
+    /// Resize to `width` x `height` and clear every tile.
+    #[func]
+    fn init(&mut self, width: i32, height: i32) {
+        self.width = width.max(0);
+        self.height = height.max(0);
+        let tiles = (self.width * self.height) as usize;
+        self.bits = vec![0; tiles.div_ceil(64)];
+    }
+
+    /// Add `pos` to the set. Out-of-bounds positions are ignored.
+    #[func]
+    fn set(&mut self, pos: Vector2i) {
+        if let Some(i) = grid_index((pos.x, pos.y), self.width, self.height) {
+            self.bits[i / 64] |= 1 << (i % 64);
+        }
+    }
+
+    /// Remove `pos` from the set. Out-of-bounds positions are ignored.
+    #[func]
+    fn clear(&mut self, pos: Vector2i) {
+        if let Some(i) = grid_index((pos.x, pos.y), self.width, self.height) {
+            self.bits[i / 64] &= !(1 << (i % 64));
+        }
+    }
+
+    /// Whether `pos` is in the set. False for out-of-bounds positions.
+    #[func]
+    fn get(&self, pos: Vector2i) -> bool {
+        grid_index((pos.x, pos.y), self.width, self.height)
+            .is_some_and(|i| self.bits[i / 64] & (1 << (i % 64)) != 0)
+    }
+
+    /// Number of tiles in the set.
+    #[func]
+    fn count(&self) -> i32 {
+        self.bits.iter().map(|w| w.count_ones() as i32).sum()
+    }
+
+    /// Tiles in the set, in row-major order.
+    #[func]
+    fn to_coords(&self) -> Array<Vector2i> {
+        let mut result = Array::new();
+        for (x, y) in self.coords() {
+            result.push(Vector2i::new(x, y));
+        }
+        result
+    }
+
+    /// New set with tiles in either set. Both sets must share dimensions.
+    #[func]
+    fn union(&self, other: Gd<HexBitset>) -> Gd<HexBitset> {
+        Gd::from_object(self.combine(&other.bind(), |a, b| a | b))
+    }
+
+    /// New set with tiles in both sets. Both sets must share dimensions.
+    #[func]
+    fn intersect(&self, other: Gd<HexBitset>) -> Gd<HexBitset> {
+        Gd::from_object(self.combine(&other.bind(), |a, b| a & b))
+    }
+
+    /// New set with tiles in this set but not in `other`. Both sets must share dimensions.
+    #[func]
+    fn difference(&self, other: Gd<HexBitset>) -> Gd<HexBitset> {
+        Gd::from_object(self.combine(&other.bind(), |a, b| a & !b))
+    }
+}
+
+impl HexBitset {
+    fn coords(&self) -> Vec<(i32, i32)> {
+        let mut coords = Vec::new();
+        for (wi, &word) in self.bits.iter().enumerate() {
+            let mut word = word;
+            while word != 0 {
+                let i = wi as i32 * 64 + word.trailing_zeros() as i32;
+                coords.push((i % self.width, i / self.width));
+                word &= word - 1;
+            }
+        }
+        coords
+    }
+
+    /// Word-wise combination; words missing from `other` are treated as empty.
+    fn combine(&self, other: &HexBitset, op: impl Fn(u64, u64) -> u64) -> HexBitset {
+        let bits = self
+            .bits
+            .iter()
+            .enumerate()
+            .map(|(i, &a)| op(a, other.bits.get(i).copied().unwrap_or(0)))
+            .collect();
+        HexBitset {
+            bits,
+            width: self.width,
+            height: self.height,
+        }
+    }
+}
+
+// ============================================================
+// 8. GameRng
+// ============================================================
+
+/// Small seeded RNG (SplitMix64) so AI and map generation are reproducible
+/// independently of Godot's global random state.
+#[derive(GodotClass)]
+#[class(base=RefCounted, init)]
+pub struct GameRng {
+    state: u64,
+}
+
+#[godot_api]
+impl GameRng {
+    /// Reset the sequence; the same seed always yields the same values.
+    #[func]
+    fn set_seed(&mut self, seed: i64) {
+        self.state = seed as u64;
+    }
+
+    /// Uniform float in [0, 1).
+    #[func]
+    fn randf(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Uniform integer in [from, to] (inclusive). Returns `from` if `to < from`.
+    #[func]
+    fn randi_range(&mut self, from: i32, to: i32) -> i32 {
+        if to <= from {
+            return from;
+        }
+        let span = (to as i64 - from as i64 + 1) as u64;
+        (from as i64 + (self.next_u64() % span) as i64) as i32
+    }
+
+    /// Scatter up to `max_points` tiles over a `width` x `height` map so that every
+    /// pair is at least `min_distance` hexes apart (tiles are visited in a shuffled
+    /// order and kept if far enough from those already kept). The same seed gives
+    /// the same points.
+    #[func]
+    fn poisson_points(
+        &mut self,
+        width: i32,
+        height: i32,
+        min_distance: i32,
+        max_points: i32,
+    ) -> Array<Vector2i> {
+        let points = self.spaced_points(width, height, min_distance, max_points);
+        path_to_array(Some(points))
+    }
+}
+
+impl GameRng {
+    fn spaced_points(
+        &mut self,
+        width: i32,
+        height: i32,
+        min_distance: i32,
+        max_points: i32,
+    ) -> Vec<(i32, i32)> {
+        let mut order: Vec<(i32, i32)> = (0..height.max(0))
+            .flat_map(|y| (0..width.max(0)).map(move |x| (x, y)))
+            .collect();
+        // Fisher-Yates shuffle
+        for i in (1..order.len()).rev() {
+            let j = (self.next_u64() % (i as u64 + 1)) as usize;
+            order.swap(i, j);
+        }
+        let mut taken = vec![false; order.len()];
+        let mut points = Vec::new();
+        for pos in order {
+            if points.len() >= max_points.max(0) as usize {
+                break;
+            }
+            let too_close = hex_disk(pos, min_distance - 1)
+                .into_iter()
+                .any(|n| grid_index(n, width, height).is_some_and(|i| taken[i]));
+            if !too_close {
+                taken[grid_index(pos, width, height).unwrap()] = true;
+                points.push(pos);
+            }
+        }
+        points
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+}
+
+// ============================================================
+// 9. UnitIndex
+// ============================================================
+
+/// Tile -> unit lookup, rebuilt once per turn and shared by queries that need to
+/// know which tiles are occupied.
+#[derive(GodotClass)]
+#[class(base=RefCounted, init)]
+pub struct UnitIndex {
+    units_by_tile: HashMap<(i32, i32), Vec<i32>>,
+}
+
+#[godot_api]
+impl UnitIndex {
+    /// Index units by tile. `positions` are grid coordinates, rounded to the nearest
+    /// tile; unit ids are their indices in `positions`.
+    #[func]
+    fn build(&mut self, positions: PackedVector2Array) {
+        let tiles: Vec<(i32, i32)> = positions
+            .as_slice()
+            .iter()
+            .map(|p| (p.x.round() as i32, p.y.round() as i32))
+            .collect();
+        self.index_tiles(&tiles);
+    }
+
+    /// Indices of the units on `tile`, in ascending order.
+    #[func]
+    fn units_at(&self, tile: Vector2i) -> PackedInt32Array {
+        self.units_by_tile
+            .get(&(tile.x, tile.y))
+            .map_or_else(PackedInt32Array::new, |units| {
+                PackedInt32Array::from(units.as_slice())
+            })
+    }
+
+    /// Whether any unit stands on `tile`.
+    #[func]
+    fn is_occupied(&self, tile: Vector2i) -> bool {
+        self.units_by_tile.contains_key(&(tile.x, tile.y))
+    }
+
+    /// Number of units on `tile`.
+    #[func]
+    fn stack_count(&self, tile: Vector2i) -> i32 {
+        self.stack_size((tile.x, tile.y)) as i32
+    }
+
+    /// Whether one more unit may enter `tile` under a limit of `max_stack` units per
+    /// tile, i.e. it currently holds fewer than `max_stack`.
+    #[func]
+    fn can_enter(&self, tile: Vector2i, max_stack: i32) -> bool {
+        usize::try_from(max_stack).is_ok_and(|max| self.stack_size((tile.x, tile.y)) < max)
+    }
+}
+
+impl UnitIndex {
+    fn index_tiles(&mut self, tiles: &[(i32, i32)]) {
+        self.units_by_tile.clear();
+        for (i, &tile) in tiles.iter().enumerate() {
+            self.units_by_tile.entry(tile).or_default().push(i as i32);
+        }
+    }
+
+    fn stack_size(&self, tile: (i32, i32)) -> usize {
+        self.units_by_tile.get(&tile).map_or(0, Vec::len)
+    }
+
+    fn occupied_tiles(&self) -> HashSet<(i32, i32)> {
+        self.units_by_tile.keys().copied().collect()
+    }
+}
+
+// ============================================================
+// 10. FogState
+// ============================================================
+
+/// Fog of war for one player: per-tile state 0 = unexplored, 1 = explored but not
+/// currently visible, 2 = visible. Row-major.
+#[derive(GodotClass)]
+#[class(base=RefCounted, init)]
+pub struct FogState {
+    states: Vec<u8>,
+    width: i32,
+    height: i32,
+}
+
+#[godot_api]
+impl FogState {
+    /// Resize to `width` x `height` with every tile unexplored.
+    #[func]
+    fn init(&mut self, width: i32, height: i32) {
+        self.width = width.max(0);
+        self.height = height.max(0);
+        self.states = vec![0; (self.width * self.height) as usize];
+    }
+
+    /// Fog state of `pos`. 0 for out-of-bounds positions.
+    #[func]
+    fn get_state(&self, pos: Vector2i) -> i32 {
+        grid_index((pos.x, pos.y), self.width, self.height).map_or(0, |i| self.states[i] as i32)
+    }
+
+    /// Apply this turn's `visible_mask` (non-zero = visible; missing entries count as
+    /// hidden) and return the tiles whose state changed, as `[Vector2i, old_state,
+    /// new_state]` entries in row-major order, so only those need redrawing.
+    #[func]
+    fn update_with_delta(&mut self, visible_mask: PackedByteArray) -> Array<Variant> {
+        let mut result = Array::new();
+        for (i, old, new) in self.apply_visibility(visible_mask.as_slice()) {
+            let x = i as i32 % self.width;
+            let y = i as i32 / self.width;
+            let mut entry = Array::<Variant>::new();
+            entry.push(&Variant::from(Vector2i::new(x, y)));
+            entry.push(&Variant::from(old as i32));
+            entry.push(&Variant::from(new as i32));
+            result.push(&Variant::from(entry));
+        }
+        result
+    }
+}
+
+impl FogState {
+    /// Update every tile from `visible_mask`; returns `(index, old, new)` per change.
+    fn apply_visibility(&mut self, visible_mask: &[u8]) -> Vec<(usize, u8, u8)> {
+        let mut changes = Vec::new();
+        for (i, state) in self.states.iter_mut().enumerate() {
+            let old = *state;
+            let new = if visible_mask.get(i).is_some_and(|&v| v != 0) {
+                2
+            } else {
+                old.min(1)
+            };
+            if new != old {
+                *state = new;
+                changes.push((i, old, new));
+            }
+        }
+        changes
+    }
+}
+
+// ============================================================
+// 11. StrategicValue
+// ============================================================
+
+/// Combined "worth fighting over" layer: resource yield, chokepoints and city
+/// proximity folded into one normalized per-tile value.
+#[derive(GodotClass)]
+#[class(base=RefCounted, init)]
+pub struct StrategicValue;
+
+#[godot_api]
+impl StrategicValue {
+    /// Row-major width*height grid in [0, 1] (0.5 everywhere if all tiles are equal).
+    /// A tile scores its total yield (food + production + gold), plus
+    /// `CHOKE_POINT_BONUS` if it is in `choke_points`, plus up to `CITY_BONUS` for
+    /// being near a city, falling off linearly to zero beyond `CITY_RANGE` tiles.
+    /// `cities` are grid coordinates, rounded to the nearest tile.
+    #[func]
+    fn compute(
+        &self,
+        tile_types: PackedInt32Array,
+        cities: PackedVector2Array,
+        choke_points: Array<Vector2i>,
+        map_width: i32,
+        map_height: i32,
+    ) -> PackedFloat32Array {
+        let cities: Vec<(i32, i32)> = cities
+            .as_slice()
+            .iter()
+            .map(|p| (p.x.round() as i32, p.y.round() as i32))
+            .collect();
+        let chokes: HashSet<(i32, i32)> = choke_points.iter_shared().map(|v| (v.x, v.y)).collect();
+        let values = strategic_values(
+            tile_types.as_slice(),
+            &cities,
+            &chokes,
+            map_width,
+            map_height,
+        );
+        PackedFloat32Array::from(values.as_slice())
+    }
+}
+
+const CHOKE_POINT_BONUS: f32 = 3.0;
+const CITY_BONUS: f32 = 3.0;
+const CITY_RANGE: i32 = 4;
+
+fn strategic_values(
+    tile_types: &[i32],
+    cities: &[(i32, i32)],
+    chokes: &HashSet<(i32, i32)>,
+    map_width: i32,
+    map_height: i32,
+) -> Vec<f32> {
+    let w = map_width.max(0);
+    let raw: Vec<f32> = (0..(w * map_height.max(0)))
+        .map(|i| {
+            let pos = (i % w, i / w);
+            let (f, p, g) = tile_yield(tile_types.get(i as usize).copied().unwrap_or(-1));
+            let mut value = (f + p + g) as f32;
+            if chokes.contains(&pos) {
+                value += CHOKE_POINT_BONUS;
+            }
+            if let Some(d) = cities.iter().map(|&c| hex_distance_xy(pos, c)).min() {
+                let falloff = 1.0 - d as f32 / (CITY_RANGE + 1) as f32;
+                value += CITY_BONUS * falloff.max(0.0);
+            }
+            value
+        })
+        .collect();
+    normalize_grid(&raw)
+}
+
+// ============================================================
+// 12. NavGrid
+// ============================================================
+
+/// Persistent pathfinding terrain for a fixed-size map: per-tile entry costs and a
+/// blocked mask, row-major. Can be baked once and shipped with a map via
+/// `save`/`load` instead of being rebuilt from tile data at load.
+#[derive(GodotClass)]
+#[class(base=RefCounted, init)]
+pub struct NavGrid {
+    width: i32,
+    height: i32,
+    costs: Vec<f64>,
+    blocked: Vec<bool>,
+    // Bumped on every terrain change so callers can drop anything derived from it
+    terrain_version: i64,
+}
+
+#[godot_api]
+impl NavGrid {
+    /// Resize to `width` x `height`. `costs[i]` is the cost of entering tile i and
+    /// `blocked[i]` non-zero marks it impassable; missing entries default to cost 1.0
+    /// and open.
+    #[func]
+    fn init(
+        &mut self,
+        width: i32,
+        height: i32,
+        costs: PackedFloat64Array,
+        blocked: PackedByteArray,
+    ) {
+        self.set_terrain(width, height, costs.as_slice(), blocked.as_slice());
+    }
+
+    /// A* path between two tiles over the stored terrain, staying on the map.
+    /// Returns empty array if no path found or either end is off the map.
+    #[func]
+    fn find_path(&self, from: Vector2i, to: Vector2i) -> Array<Vector2i> {
+        path_to_array(self.path((from.x, from.y), (to.x, to.y)))
+    }
+
+    /// Set the cost of entering `pos` in place (e.g. a road built or a forest cleared).
+    /// Off-map positions are ignored.
+    #[func]
+    fn set_tile_cost(&mut self, pos: Vector2i, cost: f64) {
+        if let Some(i) = grid_index((pos.x, pos.y), self.width, self.height) {
+            self.costs[i] = cost;
+            self.terrain_version += 1;
+        }
+    }
+
+    /// Block or unblock `pos` in place. Off-map positions are ignored.
+    #[func]
+    fn set_tile_blocked(&mut self, pos: Vector2i, blocked: bool) {
+        if let Some(i) = grid_index((pos.x, pos.y), self.width, self.height) {
+            self.blocked[i] = blocked;
+            self.terrain_version += 1;
+        }
+    }
+
+    /// Counter bumped by every terrain change (`init`, `load`, `set_tile_cost`,
+    /// `set_tile_blocked`); cached data built at an older version is stale.
+    #[func]
+    fn terrain_version(&self) -> i64 {
+        self.terrain_version
+    }
+
+    /// Terrain packed in the versioned little-endian format: `NAV_GRID_FORMAT_VERSION`
+    /// (u32), width and height (i32), width*height costs (f64), then width*height
+    /// blocked flags (u8).
+    #[func]
+    fn save(&self) -> PackedByteArray {
+        PackedByteArray::from(self.encode().as_slice())
+    }
+
+    /// Replace the terrain with data from `save`. Returns false, leaving the grid
+    /// unchanged, on a version mismatch or truncated data.
+    #[func]
+    fn load(&mut self, data: PackedByteArray) -> bool {
+        self.decode(data.as_slice())
+    }
+}
+
+/// Bumped whenever the `NavGrid::save` layout changes.
+const NAV_GRID_FORMAT_VERSION: u32 = 1;
+
+impl NavGrid {
+    fn set_terrain(&mut self, width: i32, height: i32, costs: &[f64], blocked: &[u8]) {
+        self.width = width.max(0);
+        self.height = height.max(0);
+        let n = (self.width * self.height) as usize;
+        self.costs = (0..n)
+            .map(|i| costs.get(i).copied().unwrap_or(1.0))
+            .collect();
+        self.blocked = (0..n)
+            .map(|i| blocked.get(i).is_some_and(|&b| b != 0))
+            .collect();
+        self.terrain_version += 1;
+    }
+
+    /// A* over the stored costs and blocked mask, reading them in place. `None` if
+    /// either end is off the map, `goal` is blocked, or there is no route.
+    fn path(&self, start: (i32, i32), goal: (i32, i32)) -> Option<Vec<(i32, i32)>> {
+        grid_index(start, self.width, self.height)?;
+        let goal_index = grid_index(goal, self.width, self.height)?;
+        if self.blocked[goal_index] {
+            return None;
+        }
+        astar(start, goal, SearchLimits::default(), |_, to| {
+            let i = grid_index(to, self.width, self.height)?;
+            (!self.blocked[i]).then(|| self.costs[i])
+        })
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(12 + self.costs.len() * 9);
+        data.extend_from_slice(&NAV_GRID_FORMAT_VERSION.to_le_bytes());
+        data.extend_from_slice(&self.width.to_le_bytes());
+        data.extend_from_slice(&self.height.to_le_bytes());
+        for cost in &self.costs {
+            data.extend_from_slice(&cost.to_le_bytes());
+        }
+        data.extend(self.blocked.iter().map(|&b| b as u8));
+        data
+    }
+
+    /// Load `encode` output; false (state untouched) if it does not parse.
+    fn decode(&mut self, data: &[u8]) -> bool {
+        let word = |at: usize| -> Option<[u8; 4]> { data.get(at..at + 4)?.try_into().ok() };
+        let (Some(version), Some(width), Some(height)) = (word(0), word(4), word(8)) else {
+            return false;
+        };
+        let (width, height) = (i32::from_le_bytes(width), i32::from_le_bytes(height));
+        if u32::from_le_bytes(version) != NAV_GRID_FORMAT_VERSION || width < 0 || height < 0 {
+            return false;
+        }
+        let n = width as usize * height as usize;
+        if data.len() != 12 + n * 9 {
+            return false;
+        }
+        let (cost_bytes, blocked) = data[12..].split_at(n * 8);
+        let costs: Vec<f64> = cost_bytes
+            .chunks_exact(8)
+            .map(|c| f64::from_le_bytes(c.try_into().unwrap()))
+            .collect();
+        self.set_terrain(width, height, &costs, blocked);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hex_distance_same() {
+        assert_eq!(
+            HexMath::hex_distance(Vector2i::new(0, 0), Vector2i::new(0, 0)),
+            0
+        );
+    }
+
+    #[test]
+    fn test_hex_distance_adjacent() {
+        assert_eq!(
+            HexMath::hex_distance(Vector2i::new(0, 0), Vector2i::new(1, 0)),
+            1
+        );
+    }
+
+    #[test]
+    fn test_hex_distance_far() {
+        let d = HexMath::hex_distance(Vector2i::new(0, 0), Vector2i::new(3, 3));
+        assert!(d > 0);
+    }
+
+    #[test]
+    fn test_cube_round() {
+        let (x, y, z) = cube_round(0.1, -0.2, 0.1);
+        assert_eq!(x + y + z, 0);
+    }
+
+    #[test]
+    fn test_find_path_elevation_avoids_climb() {
+        let grid = PathGrid {
+            blocked: HashSet::new(),
+            costs: HashMap::new(),
+            max_distance: 10,
+        };
+        // 5x4 map with a ridge across column 2, open at the bottom row
+        let (w, h) = (5, 4);
+        let mut heights = vec![0; 20];
+        for y in 0..3 {
+            heights[y * 5 + 2] = 5;
+        }
+        let ridge = [(2, 0), (2, 1), (2, 2)];
+
+        let flat = grid.find_path_elevation((0, 1), (4, 1), &heights, w, h, 0.0);
+        assert_eq!(flat, grid.find_path((0, 1), (4, 1)));
+        assert!(flat.unwrap().iter().any(|p| ridge.contains(p)));
+
+        let steep = grid
+            .find_path_elevation((0, 1), (4, 1), &heights, w, h, 10.0)
+            .unwrap();
+        assert!(steep.iter().all(|p| !ridge.contains(p)));
+        assert_eq!(steep.last(), Some(&(4, 1)));
+    }
+
+    #[test]
+    fn test_resource_totals_batch_matches_single() {
+        // plains, forest, mountain, water
+        let tile_types = [0, 1, 2, 3];
+        let grids = [[0, 0, 1, -1], [1, 1, 1, 1], [-1, -1, -1, -1]];
+        let batch = resource_totals_batch(&tile_types, grids.iter().map(|g| &g[..]), 2);
+        assert_eq!(batch.len(), grids.len());
+        for (grid, totals) in grids.iter().zip(&batch) {
+            assert_eq!(*totals, resource_totals(&tile_types, grid, 2));
+        }
+        assert_eq!(batch[0], vec![[1, 3, 0], [0, 3, 1]]);
+        assert_eq!(batch[1], vec![[0, 0, 0], [1, 6, 3]]);
+    }
+
+    #[test]
+    fn test_find_path_max_steps_rejects_winding_path() {
+        // Wall between (0, 0) and (2, 0); the only way round is below it.
+        let grid = PathGrid {
+            blocked: (-10..4).map(|y| (1, y)).collect(),
+            costs: HashMap::new(),
+            max_distance: 6,
+        };
+        let path = grid.find_path((0, 0), (2, 0)).unwrap();
+        let steps = path.len() - 1;
+        assert!(steps > hex_distance_xy((0, 0), (2, 0)) as usize);
+
+        let within = SearchLimits {
+            max_steps: steps,
+            ..Default::default()
+        };
+        assert_eq!(grid.find_path_limited((0, 0), (2, 0), within), Some(path));
+        let short = SearchLimits {
+            max_steps: steps - 1,
+            ..Default::default()
+        };
+        assert_eq!(grid.find_path_limited((0, 0), (2, 0), short), None);
+    }
+
+    #[test]
+    fn test_neighbor_indices_order_and_bounds() {
+        let (w, h) = (4, 3);
+        for (x, y) in [(1, 1), (2, 1)] {
+            let expected = hex_neighbors_vec(x, y).map(|(nx, ny)| ny * w + nx);
+            assert_eq!(neighbor_indices_vec(x, y, w, h), expected);
+        }
+        // Top-left corner of an even column: only (1, 0) and (0, 1) are on the map
+        assert_eq!(neighbor_indices_vec(0, 0, w, h), [1, -1, -1, -1, -1, 4]);
+    }
+
+    #[test]
+    fn test_find_path_adjacent_stops_next_to_target() {
+        let target = (4, 2);
+        let grid = PathGrid {
+            blocked: HashSet::from([target]),
+            costs: HashMap::new(),
+            max_distance: 10,
+        };
+        assert_eq!(grid.find_path((0, 2), target), None);
+        let path = grid.find_path_adjacent((0, 2), target).unwrap();
+        assert_eq!(path[0], (0, 2));
+        assert_eq!(hex_distance_xy(*path.last().unwrap(), target), 1);
+        assert!(!path.contains(&target));
+
+        let next_to = hex_neighbors_vec(4, 2)[0];
+        assert_eq!(
+            grid.find_path_adjacent(next_to, target),
+            Some(vec![next_to])
+        );
+    }
+
+    #[test]
+    fn test_cluster_tiles_two_pairs() {
+        let tiles = [(0, 0), (20, 20), (1, 0), (21, 20), (2, 0)];
+        let owners = [1, 1, 1, 1, 2];
+        let clusters = cluster_tiles(&tiles, &owners, 1, 2);
+        assert_eq!(clusters, vec![vec![0, 2], vec![1, 3]]);
+    }
+
+    #[test]
+    fn test_ring_segment() {
+        let center = (3, 4);
+        let ring = ring_segment_vec(center, 2, 4, 4);
+        assert_eq!(ring.len(), 12);
+        assert!(ring.iter().all(|&t| hex_distance_xy(center, t) == 2));
+        // Rings are walked clockwise, against neighbor order, so radius 1 is the
+        // neighbor list reversed and rotated to start at direction 4
+        let mut neighbors = hex_neighbors_vec(3, 4).to_vec();
+        neighbors.reverse();
+        neighbors.rotate_left(1);
+        assert_eq!(ring_segment_vec(center, 1, 4, 4), neighbors);
+
+        // Full-circle segment from any corner covers the same ring
+        let mut full = ring_segment_vec(center, 2, 1, 1);
+        let mut sorted_ring = ring.clone();
+        full.sort_unstable();
+        sorted_ring.sort_unstable();
+        assert_eq!(full, sorted_ring);
+
+        // Two edges from corner 1 wrap past 0 to corner 5
+        let arc = ring_segment_vec(center, 2, 1, 5);
+        assert_eq!(arc.len(), 5);
+        assert_eq!(arc[0], ring_segment_vec(center, 2, 1, 1)[0]);
+        assert_eq!(arc[4], ring_segment_vec(center, 2, 5, 5)[0]);
+    }
+
+    #[test]
+    fn test_find_path_straight_fewer_turns() {
+        fn turns(path: &[(i32, i32)]) -> usize {
+            let dirs: Vec<_> = path
+                .windows(2)
+                .map(|w| direction_between_xy(w[0], w[1]).unwrap())
+                .collect();
+            dirs.windows(2).filter(|d| d[0] != d[1]).count()
+        }
+        let grid = PathGrid {
+            blocked: HashSet::new(),
+            costs: HashMap::new(),
+            max_distance: 12,
+        };
+        let (from, to) = ((0, 0), (6, 6));
+        let plain = grid.find_path(from, to).unwrap();
+        assert_eq!(grid.find_path_straight(from, to, 0.0).unwrap(), plain);
+
+        let straight = grid.find_path_straight(from, to, 5.0).unwrap();
+        assert_eq!(straight.first(), Some(&from));
+        assert_eq!(straight.last(), Some(&to));
+        assert!(turns(&straight) < turns(&plain));
+        assert!(turns(&straight) <= 1);
+    }
+
+    #[test]
+    fn test_targets_in_range_respects_fog() {
+        let pos = [
+            Vector2::new(1.0, 1.0),
+            Vector2::new(2.0, 1.0),
+            Vector2::new(1.0, 2.0),
+        ];
+        let owners = [0, 1, 1];
+        let all = targets_in_range(&pos, &owners, 1.5, None);
+        assert!(all.chunks(2).any(|p| p == [0, 1]));
+        assert!(all.chunks(2).any(|p| p == [0, 2]));
+
+        // 3x3 mask: (2, 1) visible, (1, 2) hidden
+        let mut mask = [0u8; 9];
+        mask[5] = 1;
+        let fogged = targets_in_range(&pos, &owners, 1.5, Some((&mask, 3)));
+        assert!(fogged.chunks(2).any(|p| p == [0, 1]));
+        assert!(!fogged.chunks(2).any(|p| p == [0, 2]));
+    }
+
+    #[test]
+    fn test_cube_round_trip() {
+        for x in -3..6 {
+            for y in -3..6 {
+                let pos = Vector2i::new(x, y);
+                let cube = HexMath::offset_to_cube(pos);
+                assert_eq!(cube.x + cube.y + cube.z, 0);
+                assert_eq!(HexMath::cube_to_offset(cube), pos);
+            }
+        }
+        let snapped = HexMath::cube_to_offset(Vector3i::new(1, 1, 1));
+        let cube = HexMath::offset_to_cube(snapped);
+        assert_eq!(cube.x + cube.y + cube.z, 0);
+    }
+
+    #[test]
+    fn test_find_path_soft_prefers_open_lane() {
+        let mut grid = PathGrid {
+            blocked: HashSet::new(),
+            costs: HashMap::new(),
+            max_distance: 6,
+        };
+        let (from, to) = ((0, 2), (4, 2));
+        let direct = grid.find_path(from, to).unwrap();
+        let friend = direct[2];
+        let soft = HashSet::from([friend]);
+
+        let around = grid.find_path_soft(from, to, &soft, 5.0).unwrap();
+        assert!(!around.contains(&friend));
+        assert_eq!(around.last(), Some(&to));
+
+        // Wall off everything else in the friend's column: the friend's tile is the only way
+        grid.blocked = (-10..10).map(|y| (friend.0, y)).collect();
+        grid.blocked.remove(&friend);
+        assert_eq!(
+            grid.find_path(from, to).map(|p| p.contains(&friend)),
+            Some(true)
+        );
+        let through = grid.find_path_soft(from, to, &soft, 5.0).unwrap();
+        assert!(through.contains(&friend));
+    }
+
+    #[test]
+    fn test_shared_border_edges() {
+        // 3x2 map: column 0 is player 0, column 1 is player 1, column 2 unowned
+        let grid = [0, 1, -1, 0, 1, -1];
+        assert_eq!(shared_border_edges(&grid, 0, 1, 3, 2), 3);
+        assert_eq!(shared_border_edges(&grid, 1, 0, 3, 2), 3);
+        assert_eq!(shared_border_edges(&grid, 0, 2, 3, 2), 0);
+    }
+
+    #[test]
+    fn test_per_tile_yields_sum_to_totals() {
+        let tile_types = [0, 1, 2, 3, 4, 5, 9];
+        let owners = [0, 1, 0, -1, 1, 0, 1];
+        let grids = per_tile_yield_grids(&tile_types);
+        let totals = resource_totals(&tile_types, &owners, 2);
+        for (pid, player_totals) in totals.iter().enumerate() {
+            for (resource, grid) in grids.iter().enumerate() {
+                let sum: i32 = (0..owners.len())
+                    .filter(|&i| owners[i] == pid as i32)
+                    .map(|i| grid[i])
+                    .sum();
+                assert_eq!(sum, player_totals[resource]);
+            }
+        }
+    }
+
+    #[test]
+    fn test_flood_fill_only_connected_region() {
+        // 4x3: a wall of forest (1) in column 2 separates two plains (0) regions
+        #[rustfmt::skip]
+        let grid = [
+            0, 0, 1, 0,
+            0, 0, 1, 0,
+            0, 0, 1, 0,
+        ];
+        let filled = flood_fill_grid(&grid, (0, 0), 5, 4, 3);
+        #[rustfmt::skip]
+        let expected = [
+            5, 5, 1, 0,
+            5, 5, 1, 0,
+            5, 5, 1, 0,
+        ];
+        assert_eq!(filled, expected);
+        assert_eq!(flood_fill_grid(&grid, (0, 0), 0, 4, 3), grid);
+    }
+
+    #[test]
+    fn test_path_directions_reconstruct_path() {
+        let grid = PathGrid {
+            blocked: HashSet::from([(2, 1), (2, 2)]),
+            costs: HashMap::new(),
+            max_distance: 10,
+        };
+        let path = grid.find_path((0, 2), (5, 1)).unwrap();
+        let dirs = path_directions(&path);
+        assert_eq!(dirs.len(), path.len() - 1);
+
+        let mut tiles = vec![path[0]];
+        for d in dirs {
+            let (x, y) = *tiles.last().unwrap();
+            tiles.push(hex_neighbors_vec(x, y)[d as usize]);
+        }
+        assert_eq!(tiles, path);
+        assert!(path_directions(&[(3, 3)]).is_empty());
+    }
+
+    #[test]
+    fn test_resolve_move_claims() {
+        // Units 0 and 1 both want (2, 2); unit 1 has higher priority
+        let from = [(1, 2), (3, 2), (5, 5)];
+        let to = [(2, 2), (2, 2), (5, 5)];
+        assert_eq!(
+            resolve_move_claims(&from, &to, &[1, 3, 0]),
+            vec![false, true, false]
+        );
+        // Equal priority goes to the lower index
+        assert_eq!(
+            resolve_move_claims(&from, &to, &[2, 2, 0]),
+            vec![true, false, false]
+        );
+
+        // Unit 1 loses and stays on (3, 2), so unit 2 cannot move into it
+        let from = [(1, 2), (3, 2), (4, 2)];
+        let to = [(2, 2), (2, 2), (3, 2)];
+        assert_eq!(
+            resolve_move_claims(&from, &to, &[5, 1, 9]),
+            vec![true, false, false]
+        );
+    }
+
+    #[test]
+    fn test_bfs_distance_detours_around_wall() {
+        let (w, h) = (6, 6);
+        // Wall in column 2 from row 0 to row 4; the gap is at row 5
+        let mut blocked = [false; 36];
+        for y in 0..5 {
+            blocked[y * 6 + 2] = true;
+        }
+        let dist = bfs_distance_grid(&[(0, 0)], w, h, |i| !blocked[i]);
+        let straight = hex_distance_xy((0, 0), (4, 0));
+        assert!(dist[4] > straight);
+        assert_eq!(dist[0], 0);
+        assert_eq!(dist[2], -1);
+
+        let open = bfs_distance_grid(&[(0, 0)], w, h, |_| true);
+        assert_eq!(open[4], straight);
+    }
+
+    #[test]
+    fn test_get_influence_at() {
+        let map = InfluenceMap {
+            influence: vec![vec![0.0, 1.0, 2.0, 3.0, 4.0, 5.0]],
+            width: 3,
+            height: 2,
+            num_players: 1,
+            history: VecDeque::new(),
+            history_length: 8,
+            use_hex_distance: false,
+            passability: Vec::new(),
+            falloff: Vec::new(),
+            raw: Vec::new(),
+        };
+        assert_eq!(
+            map.get_influence_at(0, Vector2i::new(2, 1)),
+            map.influence[0][5]
+        );
+        assert_eq!(
+            map.get_influence_at(0, Vector2i::new(1, 0)),
+            map.influence[0][1]
+        );
+        assert_eq!(map.get_influence_at(0, Vector2i::new(3, 0)), 0.0);
+        assert_eq!(map.get_influence_at(1, Vector2i::new(0, 0)), 0.0);
+        assert_eq!(map.get_influence_at(-1, Vector2i::new(0, 0)), 0.0);
+    }
+
+    #[test]
+    fn test_resource_totals_by_city() {
+        // Fertile plains and forest worked by city 0, mountain and desert by city 1
+        let tile_types = [5, 1, 2, 4, 0];
+        let city_grid = [0, 0, 1, 1, -1];
+        let totals = resource_totals(&tile_types, &city_grid, 2);
+        assert_eq!(totals, vec![[3, 3, 0], [1, 3, 2]]);
+    }
+
+    #[test]
+    fn test_retreat_path_heads_for_safety() {
+        // 6x3 map, unit at (1, 1). Influence is highest on the far right column,
+        // while the nearest map edge is on the left.
+        let (w, h) = (6, 3);
+        let mut influence = vec![-2.0f32; 18];
+        for y in 0..3 {
+            for x in 0..6 {
+                influence[y * 6 + x] = x as f32 - 2.0;
+            }
+        }
+        let blocked = vec![false; 18];
+        let path = retreat_path((1, 1), &influence, &blocked, 3, w, h).unwrap();
+        assert_eq!(path[0], (1, 1));
+        assert_eq!(path.len(), 4);
+        assert_eq!(path.last().unwrap().0, 4);
+
+        // Already on the safest reachable tile: stay put
+        let path = retreat_path((5, 1), &influence, &blocked, 2, w, h).unwrap();
+        assert_eq!(path, vec![(5, 1)]);
+    }
+
+    #[test]
+    fn test_adjacency_bonus_mountain() {
+        // 5x5 plains with a mountain (2) in the middle granting +1 production
+        let (w, h) = (5, 5);
+        let mut tiles = vec![0; 25];
+        tiles[2 * 5 + 2] = 2;
+        let rules = HashMap::from([(2, [0, 1, 0])]);
+        let [food, production, gold] = adjacency_bonus_grids(&tiles, &rules, w, h);
+        assert!(food.iter().chain(&gold).all(|&v| v == 0));
+        let neighbors: Vec<usize> = hex_neighbors_vec(2, 2)
+            .iter()
+            .map(|&n| grid_index(n, w, h).unwrap())
+            .collect();
+        for (i, &p) in production.iter().enumerate() {
+            assert_eq!(p, neighbors.contains(&i) as i32, "tile {}", i);
+        }
+    }
+
+    #[test]
+    fn test_hex_bitset_set_get_count() {
+        let mut set = HexBitset {
+            bits: Vec::new(),
+            width: 0,
+            height: 0,
+        };
+        set.init(10, 9);
+        let tiles = [(0, 0), (9, 0), (3, 6), (9, 8), (3, 6)];
+        for (x, y) in tiles {
+            set.set(Vector2i::new(x, y));
+        }
+        set.set(Vector2i::new(10, 0)); // out of bounds, ignored
+        assert!(set.get(Vector2i::new(3, 6)));
+        assert!(!set.get(Vector2i::new(4, 6)));
+        assert_eq!(set.count(), 4);
+        assert_eq!(set.coords(), vec![(0, 0), (9, 0), (3, 6), (9, 8)]);
+
+        set.clear(Vector2i::new(9, 0));
+        assert!(!set.get(Vector2i::new(9, 0)));
+        assert_eq!(set.count(), 3);
+
+        let mut other = HexBitset {
+            bits: Vec::new(),
+            width: 0,
+            height: 0,
+        };
+        other.init(10, 9);
+        other.set(Vector2i::new(0, 0));
+        other.set(Vector2i::new(5, 5));
+        assert_eq!(set.combine(&other, |a, b| a | b).count(), 4);
+        assert_eq!(set.combine(&other, |a, b| a & b).coords(), vec![(0, 0)]);
+        assert_eq!(set.combine(&other, |a, b| a & !b).count(), 2);
+    }
+
+    #[test]
+    fn test_dilate_single_tile() {
+        let (w, h) = (6, 6);
+        for center in [(2, 2), (3, 3), (0, 0)] {
+            let mut mask = vec![0u8; 36];
+            mask[grid_index(center, w, h).unwrap()] = 1;
+            let grown = morph_mask(&mask, 1, w, h, true);
+            let mut expected: Vec<usize> = hex_neighbors_vec(center.0, center.1)
+                .into_iter()
+                .filter_map(|n| grid_index(n, w, h))
+                .collect();
+            expected.push(grid_index(center, w, h).unwrap());
+            expected.sort();
+            let set: Vec<usize> = (0..36).filter(|&i| grown[i] == 1).collect();
+            assert_eq!(set, expected);
+            // Eroding back recovers the single tile for an interior center
+            if center != (0, 0) {
+                assert_eq!(morph_mask(&grown, 1, w, h, false), mask);
+            }
+        }
+    }
+
+    #[test]
+    fn test_coverage_grid_union_of_disks() {
+        let (w, h) = (12, 10);
+        let cities = [(3, 4), (5, 4)];
+        let mask = coverage_grid(&cities, 2, w, h);
+        assert!(mask.iter().all(|&v| v <= 1));
+        for y in 0..h {
+            for x in 0..w {
+                let covered = cities.iter().any(|&c| hex_distance_xy(c, (x, y)) <= 2);
+                assert_eq!(mask[(y * w + x) as usize] == 1, covered, "({}, {})", x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn test_weighted_territory_bisector() {
+        // Equal-strength cities on the same row of a 10x6 map, one water tile
+        let (w, h) = (10, 6);
+        let mut tiles = vec![0; 60];
+        tiles[5] = 3;
+        let seeds = [((2, 3), 1.0, 0), ((7, 3), 1.0, 1)];
+        let owners = weighted_territory(&seeds, &tiles, &[3], w, h);
+        assert_eq!(owners[5], -1);
+        for y in 0..h {
+            for x in 0..w {
+                let i = (y * w + x) as usize;
+                if i == 5 {
                     continue;
                 }
-                let owner = owner_grid[ni];
-                if owner == player_id {
-                    continue;
+                let d0 = hex_distance_xy((2, 3), (x, y));
+                let d1 = hex_distance_xy((7, 3), (x, y));
+                let expected = if d1 < d0 { 1 } else { 0 };
+                assert_eq!(owners[i], expected, "({}, {})", x, y);
+            }
+        }
+        // Columns left of the midpoint all belong to player 0, right of it to player 1
+        assert!((0..h).all(|y| owners[(y * w) as usize] == 0));
+        assert!((0..h).all(|y| owners[(y * w + 9) as usize] == 1));
+    }
+
+    #[test]
+    fn test_find_path_traced_records_search() {
+        let grid = PathGrid {
+            blocked: [(2, 0), (2, 1), (2, 2)].into_iter().collect(),
+            costs: HashMap::new(),
+            max_distance: 10,
+        };
+        let mut trace = SearchTrace::default();
+        let path = grid
+            .find_path_traced((0, 1), (4, 1), SearchLimits::default(), Some(&mut trace))
+            .unwrap();
+        assert_eq!(Some(path.clone()), grid.find_path((0, 1), (4, 1)));
+        assert_eq!(trace.expanded[0], (0, 1));
+        for pos in &path {
+            assert!(trace.expanded.contains(pos), "{:?} not expanded", pos);
+        }
+        assert_eq!(trace.g_scores[&(4, 1)], (path.len() - 1) as f64);
+    }
+
+    #[test]
+    fn test_find_path_max_cost_prunes_expensive_terrain() {
+        // Swamp (cost 3) everywhere around the start except the start itself
+        let mut costs = HashMap::new();
+        for y in -5..6 {
+            for x in -5..6 {
+                costs.insert((x, y), 3.0);
+            }
+        }
+        let grid = PathGrid {
+            blocked: HashSet::new(),
+            costs,
+            max_distance: 4,
+        };
+        let limits = |max_cost| SearchLimits {
+            max_cost,
+            ..Default::default()
+        };
+        // Two hexes away: within max_distance, but costs 6
+        assert!(grid.find_path((0, 0), (2, 0)).is_some());
+        assert_eq!(grid.find_path_limited((0, 0), (2, 0), limits(4.0)), None);
+        assert_eq!(
+            grid.find_path_limited((0, 0), (2, 0), limits(6.0)),
+            grid.find_path((0, 0), (2, 0))
+        );
+        // max_distance still applies when the cost budget is generous
+        assert_eq!(grid.find_path_limited((0, 0), (5, 0), limits(100.0)), None);
+    }
+
+    #[test]
+    fn test_are_adjacent() {
+        for a in [Vector2i::new(4, 4), Vector2i::new(5, 4)] {
+            for n in hex_neighbors_vec(a.x, a.y) {
+                assert!(HexMath::are_adjacent(a, Vector2i::new(n.0, n.1)));
+            }
+            assert!(!HexMath::are_adjacent(a, a));
+            for ring in ring_segment_vec((a.x, a.y), 2, 0, 0) {
+                assert!(!HexMath::are_adjacent(a, Vector2i::new(ring.0, ring.1)));
+            }
+        }
+    }
+
+    #[test]
+    fn test_nearest_owner_labels_contested_midline() {
+        let (w, h) = (9, 5);
+        let blocked = vec![false; 45];
+        let (a, b) = ((0, 2), (8, 2));
+        let labels = nearest_owner_labels(&[(a, 0), (b, 1)], &blocked, w, h);
+        let da = bfs_distance_grid(&[a], w, h, |_| true);
+        let db = bfs_distance_grid(&[b], w, h, |_| true);
+        for i in 0..45 {
+            let expected = match da[i].cmp(&db[i]) {
+                Ordering::Less => 0,
+                Ordering::Greater => 1,
+                Ordering::Equal => -2,
+            };
+            assert_eq!(labels[i], expected, "tile {}", i);
+        }
+        // The middle column is equidistant from both cities
+        assert!((0..h).all(|y| labels[(y * w + 4) as usize] == -2));
+
+        // Cities of the same owner never produce a contested tile
+        let labels = nearest_owner_labels(&[(a, 0), (b, 0)], &blocked, w, h);
+        assert!(labels.iter().all(|&l| l == 0));
+    }
+
+    #[test]
+    fn test_line_cover_one_of_four() {
+        // Five hexes apart, so four intermediate tiles
+        let (w, h) = (8, 6);
+        let from = Vector2i::new(0, 2);
+        let to = Vector2i::new(5, 0);
+        let interior = line_interior(from, to);
+        assert_eq!(interior.len(), 4);
+        let mut tiles = vec![0; 48];
+        let blocked_tile = interior[1];
+        tiles[grid_index(blocked_tile, w, h).unwrap()] = 2;
+        assert_eq!(line_cover(from, to, &tiles, &[2], w, h), 0.25);
+        assert_eq!(line_cover(from, to, &tiles, &[1], w, h), 0.0);
+        assert_eq!(line_cover(from, from, &tiles, &[2], w, h), 0.0);
+    }
+
+    #[test]
+    fn test_influence_delta_between_snapshots() {
+        let mut map = InfluenceMap {
+            influence: vec![vec![1.0, 2.0, 3.0, 4.0]],
+            width: 2,
+            height: 2,
+            num_players: 1,
+            history: VecDeque::new(),
+            history_length: 2,
+            use_hex_distance: false,
+            passability: Vec::new(),
+            falloff: Vec::new(),
+            raw: Vec::new(),
+        };
+        map.push_snapshot();
+        map.influence = vec![vec![1.5, 1.0, 3.0, 6.0]];
+        map.push_snapshot();
+        assert_eq!(map.delta_grid(0, 0), Some(vec![0.0; 4]));
+        assert_eq!(map.delta_grid(0, 1), Some(vec![0.5, -1.0, 0.0, 2.0]));
+        assert_eq!(map.delta_grid(0, 2), None);
+        assert_eq!(map.delta_grid(1, 1), None);
+
+        // A third push evicts the oldest snapshot
+        map.push_snapshot();
+        assert_eq!(map.delta_grid(0, 1), Some(vec![0.0; 4]));
+        assert_eq!(map.delta_grid(0, 2), None);
+    }
+
+    #[test]
+    fn test_region_frontier() {
+        let (w, h) = (6, 6);
+        assert_eq!(
+            region_frontier(&[(2, 2)], w, h),
+            hex_neighbors_vec(2, 2).to_vec()
+        );
+        // Corner tile only has in-bounds neighbors
+        assert_eq!(region_frontier(&[(0, 0)], w, h), vec![(1, 0), (0, 1)]);
+        // Two adjacent tiles: no duplicates, neither input tile included
+        let frontier = region_frontier(&[(2, 2), (3, 2)], w, h);
+        let unique: HashSet<_> = frontier.iter().collect();
+        assert_eq!(unique.len(), frontier.len());
+        assert_eq!(frontier.len(), 8);
+        assert!(!frontier.contains(&(2, 2)) && !frontier.contains(&(3, 2)));
+    }
+
+    #[test]
+    fn test_pixel_round_trip_elongated() {
+        let size = Vector2::new(32.0, 20.0);
+        let origin = Vector2::new(100.0, -50.0);
+        for y in -3..8 {
+            for x in -3..8 {
+                let pos = Vector2i::new(x, y);
+                let center = hex_center(pos, size, origin);
+                assert_eq!(pixel_tile(center, size, origin), (x, y));
+                // Points just inside the corners still map back to the same tile
+                for i in 0..6 {
+                    let angle = (60.0 * i as f32).to_radians();
+                    let near = Vector2::new(
+                        center.x + 0.9 * size.x * angle.cos(),
+                        center.y + 0.9 * size.y * angle.sin(),
+                    );
+                    assert_eq!(pixel_tile(near, size, origin), (x, y));
                 }
-                // Skip water (type check not available here — caller filters or we accept all non-owned)
-                if frontier_set.insert((nx, ny)) {
-                    result.push(Vector2i::new(nx, ny));
+            }
+        }
+    }
+
+    #[test]
+    fn test_hex_line_symmetric() {
+        for a in [Vector2i::new(3, 3), Vector2i::new(4, 3)] {
+            for y in 0..8 {
+                for x in 0..8 {
+                    let b = Vector2i::new(x, y);
+                    let forward = hex_line_xy(a, b);
+                    let mut backward = hex_line_xy(b, a);
+                    backward.reverse();
+                    assert_eq!(forward, backward, "{:?} -> {:?}", a, b);
+                    assert_eq!(forward.len() as i32, HexMath::hex_distance(a, b) + 1);
+                    assert_eq!(forward[0], (a.x, a.y));
+                    for pair in forward.windows(2) {
+                        assert_eq!(hex_distance_xy(pair[0], pair[1]), 1);
+                    }
                 }
             }
         }
-        result
     }
-}
 
-// ============================================================
-// 3. CombatQuery
-// ============================================================
+    #[test]
+    fn test_hex_lerp_endpoints() {
+        let pairs = [((0, 0), (5, 3)), ((4, 7), (1, 2)), ((3, 3), (3, 3))];
+        for ((ax, ay), (bx, by)) in pairs {
+            let (a, b) = (Vector2i::new(ax, ay), Vector2i::new(bx, by));
+            assert_eq!(HexMath::hex_lerp(a, b, 0.0), a);
+            assert_eq!(HexMath::hex_lerp(a, b, 1.0), b);
+        }
+        // Halfway along a straight column
+        let mid = HexMath::hex_lerp(Vector2i::new(2, 0), Vector2i::new(2, 4), 0.5);
+        assert_eq!(mid, Vector2i::new(2, 2));
+    }
+
+    #[test]
+    fn test_find_path_bounded_aborts() {
+        let grid = PathGrid {
+            blocked: (-20..19).map(|y| (6, y)).collect(),
+            costs: HashMap::new(),
+            max_distance: 30,
+        };
+        let limits = |max_expansions| SearchLimits {
+            max_expansions,
+            ..Default::default()
+        };
+        let full = grid.find_path((0, 0), (12, 0));
+        assert!(full.is_some());
+        assert_eq!(grid.find_path_limited((0, 0), (12, 0), limits(5)), None);
+
+        let mut trace = SearchTrace::default();
+        grid.find_path_traced((0, 0), (12, 0), SearchLimits::default(), Some(&mut trace));
+        let needed = trace.expanded.len();
+        assert_eq!(
+            grid.find_path_limited((0, 0), (12, 0), limits(needed)),
+            full
+        );
+    }
+
+    #[test]
+    fn test_normalize_grid() {
+        let grid = [-3.0, 0.5, 7.0, -1.0, 2.0];
+        let normalized = normalize_grid(&grid);
+        assert!(normalized.iter().all(|&v| (0.0..=1.0).contains(&v)));
+        assert_eq!(normalized[0], 0.0);
+        assert_eq!(normalized[2], 1.0);
+        for i in 0..grid.len() {
+            for j in 0..grid.len() {
+                assert_eq!(grid[i] < grid[j], normalized[i] < normalized[j]);
+            }
+        }
+        assert_eq!(normalize_grid(&[2.0, 2.0, 2.0]), vec![0.5; 3]);
+    }
+
+    #[test]
+    fn test_find_path_best_goal_tradeoff() {
+        let mut grid = PathGrid {
+            blocked: HashSet::new(),
+            costs: HashMap::new(),
+            max_distance: 20,
+        };
+        let goals = [(2, 0), (10, 0)];
+        // Cheap travel: the far goal's extra value outweighs 8 more moves
+        let (path, index, score) = grid
+            .find_path_best_goal((0, 0), &goals, &[5.0, 20.0])
+            .unwrap();
+        assert_eq!(index, 1);
+        assert_eq!(score, 10.0);
+        assert_eq!(path.len(), grid.find_path((0, 0), (10, 0)).unwrap().len());
+        assert_eq!(path.last(), Some(&(10, 0)));
+
+        // Expensive terrain: the near goal wins
+        for y in -10..10 {
+            for x in -10..20 {
+                grid.costs.insert((x, y), 3.0);
+            }
+        }
+        let (path, index, score) = grid
+            .find_path_best_goal((0, 0), &goals, &[5.0, 20.0])
+            .unwrap();
+        assert_eq!(index, 0);
+        assert_eq!(score, -1.0);
+        assert_eq!(path.last(), Some(&(2, 0)));
+
+        grid.blocked = goals.into_iter().collect();
+        assert!(grid
+            .find_path_best_goal((0, 0), &goals, &[5.0, 20.0])
+            .is_none());
+    }
+
+    #[test]
+    fn test_border_loops() {
+        let (w, h) = (8, 8);
+        // Blob: a tile plus its six neighbors has 18 boundary edges
+        let mut grid = vec![-1; 64];
+        for (x, y) in hex_neighbors_vec(3, 3).into_iter().chain([(3, 3)]) {
+            grid[(y * w + x) as usize] = 0;
+        }
+        let loops = border_loops(&grid, 0, w, h);
+        assert_eq!(loops.len(), 1);
+        assert_eq!(loops[0].len(), 18);
+        // Consecutive points (wrapping) are corners one unit-size edge apart
+        for (i, &a) in loops[0].iter().enumerate() {
+            let b = loops[0][(i + 1) % 18];
+            let (dx, dy) = ((b.0 - a.0) as f32 * 0.5, (b.1 - a.1) as f32 * 0.866_025_4);
+            assert!(((dx * dx + dy * dy).sqrt() - 1.0).abs() < 1e-4);
+        }
+
+        // Punch out the middle: the outer loop plus a six-edge hole
+        grid[(3 * w + 3) as usize] = 1;
+        let mut lens: Vec<usize> = border_loops(&grid, 0, w, h).iter().map(Vec::len).collect();
+        lens.sort();
+        assert_eq!(lens, vec![6, 18]);
+    }
+
+    #[test]
+    fn test_axial_mode_matches_offset() {
+        let axial_of = |p: (i32, i32)| to_axial(Vector2i::new(p.0, p.1));
+        let blocked: Vec<(i32, i32)> = (-3..5).map(|y| (3, y)).chain([(1, 4), (5, 0)]).collect();
+        let costs: Vec<((i32, i32), f64)> = vec![((2, 5), 4.0), ((4, 5), 2.5), ((6, 2), 3.0)];
+        let offset = PathGrid {
+            blocked: blocked.iter().copied().collect(),
+            costs: costs.iter().copied().collect(),
+            max_distance: 12,
+        };
+        let axial = PathGrid {
+            blocked: blocked.iter().map(|&p| axial_of(p)).collect(),
+            costs: costs.iter().map(|&(p, c)| (axial_of(p), c)).collect(),
+            max_distance: 12,
+        };
+        for (x, y) in [(2, 2), (4, 1)] {
+            assert_eq!(
+                axial_neighbors(axial_of((x, y))).to_vec(),
+                hex_neighbors_vec(x, y).map(axial_of).to_vec()
+            );
+        }
+        for goal in [(7, 1), (6, 6), (0, -2)] {
+            let expected = offset
+                .find_path((0, 0), goal)
+                .map(|path| path.into_iter().map(axial_of).collect::<Vec<_>>());
+            assert!(expected.is_some());
+            assert_eq!(
+                axial.find_path_axial(axial_of((0, 0)), axial_of(goal)),
+                expected
+            );
+        }
+        let expected: HashSet<(i32, i32)> = offset
+            .reachable((2, 2), 4.0, false)
+            .into_iter()
+            .map(axial_of)
+            .collect();
+        let actual: HashSet<(i32, i32)> = axial
+            .reachable(axial_of((2, 2)), 4.0, true)
+            .into_iter()
+            .collect();
+        assert_eq!(actual, expected);
+        assert!(expected.len() > 1);
+    }
+
+    #[test]
+    fn test_sample_positive_single_cell() {
+        let mut rng = GameRng { state: 0 };
+        rng.set_seed(42);
+        let grid = [-1.0, 0.0, 3.5, -0.5, 0.0, -2.0];
+        for _ in 0..50 {
+            assert_eq!(sample_positive(&grid, rng.randf()), Some(2));
+        }
+        assert_eq!(sample_positive(&[-1.0, 0.0], rng.randf()), None);
+        // Draws follow the cumulative weights
+        assert_eq!(sample_positive(&[1.0, 3.0], 0.2), Some(0));
+        assert_eq!(sample_positive(&[1.0, 3.0], 0.3), Some(1));
+
+        // Same seed, same sequence
+        let (mut a, mut b) = (GameRng { state: 0 }, GameRng { state: 0 });
+        a.set_seed(7);
+        b.set_seed(7);
+        for _ in 0..10 {
+            assert_eq!(a.randi_range(-5, 5), b.randi_range(-5, 5));
+        }
+    }
+
+    #[test]
+    fn test_firing_tiles_need_line_of_sight() {
+        let (w, h) = (10, 8);
+        let mut tiles = vec![0; 80];
+        let target = (7, 3);
+        // Mountain right next to the target on its west side
+        let mountain = (6, 3);
+        tiles[grid_index(mountain, w, h).unwrap()] = 2;
+        let grid = PathGrid {
+            blocked: HashSet::from([mountain]),
+            costs: HashMap::new(),
+            max_distance: i32::MAX,
+        };
+        let found = firing_tiles(&grid, (3, 3), target, 2, 3.0, &tiles, w, h);
+        let reachable = grid.reachable((3, 3), 3.0, false);
+        assert!(!found.is_empty());
+        for &pos in &reachable {
+            let in_range = hex_distance_xy(pos, target) <= 2;
+            let clear = !line_interior(Vector2i::new(pos.0, pos.1), Vector2i::new(7, 3))
+                .contains(&mountain);
+            assert_eq!(found.contains(&pos), in_range && clear, "{:?}", pos);
+        }
+        // Some reachable in-range tiles are excluded because of the mountain
+        assert!(reachable.iter().any(|&pos| {
+            hex_distance_xy(pos, target) <= 2
+                && line_interior(Vector2i::new(pos.0, pos.1), Vector2i::new(7, 3))
+                    .contains(&mountain)
+        }));
+
+        // Target on the west edge: off-map tiles next to it are never firing spots
+        let edge_target = (0, 3);
+        let open = PathGrid {
+            blocked: HashSet::new(),
+            costs: HashMap::new(),
+            max_distance: i32::MAX,
+        };
+        assert!(open.reachable((2, 3), 3.0, false).contains(&(-1, 3)));
+        let found = firing_tiles(&open, (2, 3), edge_target, 1, 3.0, &tiles, w, h);
+        assert!(!found.is_empty());
+        assert!(found.iter().all(|&pos| grid_index(pos, w, h).is_some()));
+    }
+
+    #[test]
+    fn test_exposure_counts_single_enemy() {
+        let (w, h) = (10, 10);
+        let mut tiles = vec![0; 100];
+        let wall = (5, 4);
+        tiles[grid_index(wall, w, h).unwrap()] = 2;
+        let enemy = (4, 4);
+        let counts = exposure_counts(&[enemy], &[3], &tiles, &[2], w, h);
+        for y in 0..h {
+            for x in 0..w {
+                let from = Vector2i::new(enemy.0, enemy.1);
+                let visible = hex_distance_xy(enemy, (x, y)) <= 3
+                    && !line_interior(from, Vector2i::new(x, y)).contains(&wall);
+                assert_eq!(
+                    counts[(y * w + x) as usize],
+                    visible as i32,
+                    "({}, {})",
+                    x,
+                    y
+                );
+            }
+        }
+        // The tile straight behind the wall is in range but hidden
+        let behind = from_axial(6, to_axial(Vector2i::new(4, 4)).1);
+        assert_eq!(hex_distance_xy(enemy, behind), 2);
+        assert_eq!(counts[grid_index(behind, w, h).unwrap()], 0);
+
+        // Two enemies stack
+        let counts = exposure_counts(&[enemy, enemy], &[3, 3], &tiles, &[2], w, h);
+        assert_eq!(counts[grid_index(enemy, w, h).unwrap()], 2);
+    }
+
+    #[test]
+    fn test_incremental_resource_totals() {
+        let tiles = vec![0, 1, 2, 3, 4, 5, 0, 2, 1];
+        let owners = vec![0, 0, 1, -1, 1, 0, -1, -1, 1];
+        let mut counter = ResourceCounter {
+            tile_types: tiles.clone(),
+            owner_grid: owners.clone(),
+            totals: resource_totals(&tiles, &owners, 2),
+        };
+        let mut expected = owners;
+        for (index, owner) in [(3, 0), (2, 0), (5, -1), (8, 1), (0, 1), (100, 0)] {
+            counter.change_owner(index, owner);
+            if let Some(o) = expected.get_mut(index as usize) {
+                *o = owner;
+            }
+            assert_eq!(counter.totals, resource_totals(&tiles, &expected, 2));
+        }
+    }
+
+    #[test]
+    fn test_spiral_index_round_trip() {
+        let origin = Vector2i::new(0, 0);
+        assert_eq!(HexMath::spiral_index(origin), 0);
+        for (d, (x, y)) in hex_neighbors_vec(0, 0).into_iter().enumerate() {
+            assert_eq!(HexMath::spiral_index(Vector2i::new(x, y)), d as i32 + 1);
+        }
+        for index in 0..400 {
+            let pos = HexMath::from_spiral_index(index);
+            assert_eq!(HexMath::spiral_index(pos), index);
+        }
+        // Ring k holds indices [1 + 3k(k-1), 1 + 3k(k+1))
+        for y in -8..9 {
+            for x in -8..9 {
+                let pos = Vector2i::new(x, y);
+                let k = HexMath::hex_distance(origin, pos);
+                let index = HexMath::spiral_index(pos);
+                assert!(index >= if k == 0 { 0 } else { 1 + 3 * k * (k - 1) });
+                assert!(index < 1 + 3 * k * (k + 1));
+                assert_eq!(HexMath::from_spiral_index(index), pos);
+            }
+        }
+    }
+
+    #[test]
+    fn test_frontier_by_cost_cheapest_first() {
+        let (w, h) = (8, 6);
+        // Player 0 owns a vertical strip at x = 2..=3
+        let mut owners = vec![-1; 48];
+        for y in 0..h {
+            for x in 2..4 {
+                owners[(y * w + x) as usize] = 0;
+            }
+        }
+        let mut tiles = vec![0; 48];
+        tiles[(2 * w + 4) as usize] = 3; // water next to the capital
+        let mut grid = PathGrid {
+            blocked: impassable_tiles(&tiles, &[3], w, h),
+            costs: HashMap::new(),
+            max_distance: i32::MAX,
+        };
+        // Expensive forest covers the west side
+        for y in 0..h {
+            grid.costs.insert((1, y), 5.0);
+        }
+        let ranked = frontier_by_cost(&grid, &owners, 0, (3, 2), w, h);
+        assert!(ranked.windows(2).all(|p| p[0].1 <= p[1].1));
+        assert!(!ranked.iter().any(|&(pos, _)| pos == (4, 2)));
+        assert_eq!(ranked[0].1, 1.0);
+        assert_eq!(hex_distance_xy(ranked[0].0, (3, 2)), 1);
+        assert!(ranked
+            .iter()
+            .all(|&(pos, _)| owners[(pos.1 * w + pos.0) as usize] != 0));
+        let west = ranked.iter().find(|&&(pos, _)| pos == (1, 2)).unwrap();
+        assert!(west.1 >= 5.0);
+        // Capitals on or beyond the blocked ring rank nothing
+        assert!(frontier_by_cost(&grid, &owners, 0, (-1, 2), w, h).is_empty());
+        assert!(frontier_by_cost(&grid, &owners, 0, (-3, 5), w, h).is_empty());
+    }
+
+    #[test]
+    fn test_find_path_waypoints_joins_legs() {
+        let mut grid = PathGrid {
+            blocked: HashSet::new(),
+            costs: HashMap::new(),
+            max_distance: 10,
+        };
+        // Three collinear waypoints down one column
+        let path = grid.find_path_waypoints(&[(2, 0), (2, 3), (2, 6)]).unwrap();
+        assert_eq!(path, (0..7).map(|y| (2, y)).collect::<Vec<_>>());
+        assert_eq!(grid.find_path_waypoints(&[(4, 4)]), Ok(vec![(4, 4)]));
+
+        grid.blocked.insert((2, 6));
+        assert_eq!(grid.find_path_waypoints(&[(2, 0), (2, 3), (2, 6)]), Err(1));
+    }
+
+    #[test]
+    fn test_path_avoiding_units() {
+        let mut index = UnitIndex {
+            units_by_tile: HashMap::new(),
+        };
+        // Mover at (2, 0), another unit straight ahead at (2, 2)
+        index.index_tiles(&[(2, 0), (2, 2)]);
+        assert_eq!(index.units_by_tile[&(2, 2)], vec![1]);
+        let occupied = index.occupied_tiles();
+
+        let around = path_avoiding((2, 0), (2, 4), occupied.clone(), -1.0, 10).unwrap();
+        assert!(!around.contains(&(2, 2)));
+        assert_eq!(around.first(), Some(&(2, 0)));
+        assert_eq!(around.last(), Some(&(2, 4)));
+
+        // A cheap penalty keeps the straight route, a large one detours
+        let straight = path_avoiding((2, 0), (2, 4), occupied.clone(), 0.5, 10).unwrap();
+        assert!(straight.contains(&(2, 2)));
+        let detour = path_avoiding((2, 0), (2, 4), occupied.clone(), 5.0, 10).unwrap();
+        assert!(!detour.contains(&(2, 2)));
+
+        assert_eq!(path_avoiding((2, 0), (2, 2), occupied, -1.0, 10), None);
+    }
+
+    #[test]
+    fn test_step_and_back() {
+        for from in [Vector2i::new(4, 4), Vector2i::new(5, 4)] {
+            for d in 0..6 {
+                let there = HexMath::step(from, d);
+                assert_eq!(HexMath::direction_between(from, there), d);
+                assert_eq!(HexMath::dir_to_offset(there, (d + 3) % 6), from);
+            }
+            assert_eq!(HexMath::step(from, 7), HexMath::step(from, 1));
+            assert_eq!(HexMath::step(from, -1), HexMath::step(from, 5));
+        }
+    }
+
+    #[test]
+    fn test_find_path_margin_keeps_distance() {
+        let danger = (5, 3);
+        let margin: HashSet<(i32, i32)> = hex_disk(danger, 1).into_iter().collect();
+        let mut grid = PathGrid {
+            blocked: HashSet::new(),
+            costs: HashMap::new(),
+            max_distance: 12,
+        };
+        let direct = grid.find_path((2, 3), (8, 3)).unwrap();
+        assert!(direct.iter().any(|&p| hex_distance_xy(p, danger) <= 1));
+        let safe = grid.find_path_margin((2, 3), (8, 3), &margin).unwrap();
+        assert_eq!(safe.last(), Some(&(8, 3)));
+        assert!(safe.iter().all(|&p| hex_distance_xy(p, danger) > 1));
+
+        // Walls leave only a corridor through the danger zone
+        for y in -12..15 {
+            if y != 3 {
+                grid.blocked.insert((5, y));
+            }
+        }
+        let forced = grid.find_path_margin((2, 3), (8, 3), &margin).unwrap();
+        assert!(forced.contains(&(5, 3)));
+        let close = forced
+            .iter()
+            .filter(|&&p| hex_distance_xy(p, danger) <= 1)
+            .count();
+        assert_eq!(close, 3);
+    }
+
+    #[test]
+    fn test_targets_in_range_parallel_matches_serial() {
+        let mut rng = GameRng { state: 0 };
+        rng.set_seed(1234);
+        let positions: Vec<Vector2> = (0..300)
+            .map(|_| Vector2::new(rng.randf() as f32 * 60.0, rng.randf() as f32 * 60.0))
+            .collect();
+        let owners: Vec<i32> = (0..300).map(|_| rng.randi_range(0, 3)).collect();
+        let serial = targets_in_range(&positions, &owners, 4.0, None);
+        assert!(!serial.is_empty());
+        assert_eq!(targets_in_range_parallel(&positions, &owners, 4.0), serial);
+    }
+
+    #[test]
+    fn test_bounding_rect() {
+        let rect = bounding_rect(&[(5, -2), (1, 4)]);
+        assert_eq!(rect.position, Vector2i::new(1, -2));
+        assert_eq!(rect.size, Vector2i::new(5, 7));
+        assert_eq!(rect.end(), Vector2i::new(6, 5));
+        assert_eq!(bounding_rect(&[(3, 3)]).size, Vector2i::new(1, 1));
+        assert_eq!(bounding_rect(&[]), Rect2i::default());
+    }
+
+    #[test]
+    fn test_front_chains_symmetric_armies() {
+        // Player 0 leads on the left half of an 8x6 map, player 1 on the right
+        let (w, h) = (8usize, 6usize);
+        let lead: Vec<f32> = (0..w * h).map(|i| 3.5 - (i % w) as f32).collect();
+        let map = InfluenceMap {
+            influence: vec![lead.clone(), lead.iter().map(|v| -v).collect()],
+            width: w,
+            height: h,
+            num_players: 2,
+            history: VecDeque::new(),
+            history_length: 8,
+            use_hex_distance: false,
+            passability: Vec::new(),
+            falloff: Vec::new(),
+            raw: Vec::new(),
+        };
+        let lines = map.front_chains(0, 1);
+        assert_eq!(lines.len(), 1);
+        let size = Vector2::new(1.0, 1.0);
+        let left = hex_center(Vector2i::new(3, 0), size, Vector2::new(0.0, 0.0)).x;
+        let right = hex_center(Vector2i::new(4, 0), size, Vector2::new(0.0, 0.0)).x;
+        for &key in &lines[0] {
+            let x = corner_pixel(key, size).x;
+            assert!(x > left && x < right, "{} outside ({}, {})", x, left, right);
+        }
+        // Runs top to bottom, keeping player 0 (west) on its right
+        let (first, last) = (lines[0][0], *lines[0].last().unwrap());
+        assert!(corner_pixel(first, size).y < corner_pixel(last, size).y);
+        assert!(map.front_chains(0, 5).is_empty());
+    }
+
+    #[test]
+    fn test_hex_distance_falloff_is_circular() {
+        let (w, h) = (20, 20);
+        for center in [(9, 9), (10, 9)] {
+            let mut offset = vec![0.0f32; w * h];
+            add_falloff(&mut offset, w, h, center, 2.0, 4.0, false, &[]);
+            let mut hex = vec![0.0f32; w * h];
+            add_falloff(&mut hex, w, h, center, 2.0, 4.0, true, &[]);
+            let at = |grid: &[f32], (x, y): (i32, i32)| grid[y as usize * w + x as usize];
+            let ring = hex_neighbors_vec(center.0, center.1);
+            // Offset-space distance gives the diagonal neighbors less influence
+            assert!(ring.iter().any(|&n| at(&offset, n) != at(&offset, ring[2])));
+            for n in ring {
+                assert_eq!(at(&hex, n), at(&hex, ring[0]));
+            }
+            assert_eq!(at(&hex, center), 2.0);
+        }
+    }
+
+    #[test]
+    fn test_claimable_excludes_dominated_tiles() {
+        let (w, h) = (6, 3);
+        // Player 0 holds column 0; column 1 is the frontier
+        let mut owners = vec![-1; 18];
+        let mut influence = vec![1.0f32; 18];
+        for y in 0..3 {
+            owners[y * 6] = 0;
+        }
+        owners[6 + 1] = 1; // enemy tile (1, 1) ...
+        influence[6 + 1] = -0.5; // ... where the enemy dominates
+        let mut tiles = vec![0; 18];
+        tiles[2 * 6 + 1] = 3; // water at (1, 2)
+        let found = claimable(&owners, &influence, 0, &tiles, &[3], 0.2, w, h);
+        assert_eq!(found, vec![(1, 0)]);
+
+        // A weakly held enemy tile is claimable
+        influence[6 + 1] = 0.5;
+        let found = claimable(&owners, &influence, 0, &tiles, &[3], 0.2, w, h);
+        assert_eq!(found, vec![(1, 0), (1, 1)]);
+        // ... unless the threshold is higher than our lead
+        let found = claimable(&owners, &influence, 0, &tiles, &[3], 0.6, w, h);
+        assert_eq!(found, vec![(1, 0)]);
+    }
+
+    #[test]
+    fn test_find_path_ordered_reverse() {
+        let grid = PathGrid {
+            blocked: [(2, 1), (2, 2), (3, 3)].into_iter().collect(),
+            costs: HashMap::from([((1, 3), 2.0)]),
+            max_distance: 10,
+        };
+        let forward = grid.find_path_ordered((0, 1), (5, 3), false).unwrap();
+        assert_eq!(Some(forward.clone()), grid.find_path((0, 1), (5, 3)));
+        let mut backward = grid.find_path_ordered((0, 1), (5, 3), true).unwrap();
+        assert_eq!(backward[0], (5, 3));
+        backward.reverse();
+        assert_eq!(backward, forward);
+    }
+
+    #[test]
+    fn test_owned_region_stats_l_shape() {
+        let (w, h) = (8, 8);
+        let mut grid = vec![-1; 64];
+        // L: three tiles down column 2, then (3, 4) and (4, 4) along the bottom
+        for pos in [(2, 2), (2, 3), (2, 4), (3, 4), (4, 4)] {
+            grid[(pos.1 * w + pos.0) as usize] = 0;
+        }
+        // A separate single tile
+        grid[7] = 0;
+        // Shared edges inside the L: (2,2)-(2,3), (2,3)-(2,4), (2,4)-(3,4) and
+        // (3,4)-(4,4), so 5 tiles * 6 - 2 * 4 = 22 exposed edges.
+        assert_eq!(owned_region_stats(&grid, 0, w, h), (5, 22, 2));
+        assert_eq!(owned_region_stats(&grid, 1, w, h), (0, 0, 0));
+    }
+
+    #[test]
+    fn test_spaced_points_spacing_and_seed() {
+        let sample = |seed| {
+            let mut rng = GameRng { state: 0 };
+            rng.set_seed(seed);
+            rng.spaced_points(30, 20, 4, 40)
+        };
+        let points = sample(99);
+        assert!(points.len() > 5 && points.len() <= 40);
+        for (i, &a) in points.iter().enumerate() {
+            assert!(a.0 >= 0 && a.0 < 30 && a.1 >= 0 && a.1 < 20);
+            for &b in &points[i + 1..] {
+                assert!(hex_distance_xy(a, b) >= 4, "{:?} {:?}", a, b);
+            }
+        }
+        assert_eq!(sample(99), points);
+        assert_ne!(sample(100), points);
+    }
+
+    #[test]
+    fn test_is_path_valid() {
+        let grid = PathGrid {
+            blocked: HashSet::from([(3, 3)]),
+            costs: HashMap::from([((2, 2), 2.0)]),
+            max_distance: i32::MAX,
+        };
+        let legal = [(2, 0), (2, 1), (2, 2), (2, 3)];
+        assert!(grid.is_path_valid(&legal, 4.0, 8, 8));
+        assert!(!grid.is_path_valid(&legal, 3.5, 8, 8));
+        // Jumps two tiles
+        assert!(!grid.is_path_valid(&[(2, 0), (2, 2)], 10.0, 8, 8));
+        // Enters a blocked tile / leaves the map
+        assert!(!grid.is_path_valid(&[(2, 3), (3, 3)], 10.0, 8, 8));
+        assert!(!grid.is_path_valid(&[(0, 0), (0, -1)], 10.0, 8, 8));
+        assert!(grid.is_path_valid(&[(5, 5)], 0.0, 8, 8));
+        assert!(!grid.is_path_valid(&[], 10.0, 8, 8));
+    }
+
+    #[test]
+    fn test_find_path_with_cost_fn() {
+        let grid = PathGrid {
+            blocked: HashSet::from([(3, 2), (3, 3), (4, 2)]),
+            costs: HashMap::new(),
+            max_distance: 10,
+        };
+        let mut calls = HashMap::new();
+        let lazy = grid.find_path_with((1, 1), (6, 4), |pos| {
+            *calls.entry(pos).or_insert(0) += 1;
+            1.0
+        });
+        assert_eq!(lazy, grid.find_path((1, 1), (6, 4)));
+        assert!(calls.values().all(|&n| n == 1));
+    }
+
+    #[test]
+    fn test_fog_apply_visibility_delta() {
+        let mut fog = FogState {
+            states: vec![0; 12],
+            width: 4,
+            height: 3,
+        };
+        let mut mask = vec![0u8; 12];
+        mask[5] = 1;
+        assert_eq!(fog.apply_visibility(&mask), vec![(5, 0, 2)]);
+        // Nothing changed since last turn
+        assert!(fog.apply_visibility(&mask).is_empty());
+
+        // Reveal one more tile; the first stays visible
+        mask[6] = 1;
+        assert_eq!(fog.apply_visibility(&mask), vec![(6, 0, 2)]);
+
+        // Moving away leaves explored tiles behind
+        let mask = vec![0u8; 12];
+        assert_eq!(fog.apply_visibility(&mask), vec![(5, 2, 1), (6, 2, 1)]);
+        assert_eq!(fog.get_state(Vector2i::new(1, 1)), 1);
+        assert_eq!(fog.get_state(Vector2i::new(0, 0)), 0);
+    }
+
+    #[test]
+    fn test_convex_hull_of_disk_and_degenerate_sets() {
+        let lattice = |pos: (i32, i32)| {
+            let (q, r) = to_axial(Vector2i::new(pos.0, pos.1));
+            (3 * q as i64, 2 * r as i64 + q as i64)
+        };
+        let (cq, cr) = to_axial(Vector2i::new(4, 4));
+        let hull = hull_lattice(&hex_disk((4, 4), 2));
+        let corners: HashSet<(i64, i64)> = AXIAL_DIRS
+            .iter()
+            .map(|&(dq, dr)| lattice(from_axial(cq + 2 * dq, cr + 2 * dr)))
+            .collect();
+        assert_eq!(hull.len(), 6);
+        assert_eq!(hull.iter().copied().collect::<HashSet<_>>(), corners);
+
+        assert!(hull_lattice(&[]).is_empty());
+        assert_eq!(hull_lattice(&[(2, 2), (2, 2)]), vec![lattice((2, 2))]);
+        // A straight column collapses to its two ends
+        let column = [(3, 0), (3, 1), (3, 2), (3, 3)];
+        assert_eq!(
+            hull_lattice(&column),
+            vec![lattice((3, 0)), lattice((3, 3))]
+        );
+    }
+
+    #[test]
+    fn test_assign_tiles_minimizes_total_distance() {
+        // Greedy in unit order sends unit 0 to the close objective 0 and unit 1 all
+        // the way to objective 1 (total 6); the optimum uncrosses them (total 4).
+        let units = [(0, 3), (0, 0)];
+        let objectives = [(0, 2), (0, 5)];
+        assert_eq!(assign_tiles(&units, &objectives), vec![1, 0]);
+
+        // More units than objectives: the farthest unit is left out.
+        let units = [(0, 0), (5, 5), (0, 1)];
+        assert_eq!(assign_tiles(&units, &[(0, 2), (1, 0)]), vec![1, -1, 0]);
+
+        // Matches brute force on a 4x4 case.
+        let units = [(0, 0), (3, 1), (6, 6), (2, 5)];
+        let objectives = [(5, 5), (1, 1), (2, 2), (0, 6)];
+        let total = |a: &[i32]| -> i32 {
+            (0..4)
+                .map(|i| hex_distance_xy(units[i], objectives[a[i] as usize]))
+                .sum()
+        };
+        let mut best = i32::MAX;
+        for p in 0..256 {
+            let a: Vec<i32> = (0..4).map(|k| (p >> (2 * k)) & 3).collect();
+            if a.iter().collect::<HashSet<_>>().len() == 4 {
+                best = best.min(total(&a));
+            }
+        }
+        assert_eq!(total(&assign_tiles(&units, &objectives)), best);
+    }
+
+    #[test]
+    fn test_incremental_frontier_matches_full_recompute() {
+        let (w, h) = (7, 6);
+        let mut grid = vec![-1; (w * h) as usize];
+        grid[(2 * w + 3) as usize] = 0;
+        grid[(3 * w + 3) as usize] = 1;
+        let mut frontier = TerritoryFrontier {
+            owner_grid: Vec::new(),
+            width: 0,
+            height: 0,
+            tracked_player: 0,
+            frontier: BTreeSet::new(),
+        };
+        frontier.track_owner_grid(&grid, 0, w, h);
+        // Full recompute: every tile not owned by 0 with an in-bounds neighbor owned by 0
+        let full = |grid: &[i32]| -> BTreeSet<usize> {
+            (0..grid.len())
+                .filter(|&i| {
+                    grid[i] != 0
+                        && hex_neighbors_vec(i as i32 % w, i as i32 / w)
+                            .into_iter()
+                            .any(|n| grid_index(n, w, h).is_some_and(|ni| grid[ni] == 0))
+                })
+                .collect()
+        };
+        assert_eq!(frontier.frontier, full(&grid));
+        for (index, owner) in [
+            (16, 0),
+            (0, 0),
+            (24, 1),
+            (17, 0),
+            (16, -1),
+            (24, 0),
+            (41, 0),
+        ] {
+            grid[index as usize] = owner;
+            frontier.set_tile_owner(index, owner);
+            assert_eq!(frontier.frontier, full(&grid));
+        }
+    }
+
+    #[test]
+    fn test_strategic_value_ranks_fertile_choke_near_city_highest() {
+        let (w, h) = (8, 8);
+        let mut tiles = vec![0; 64];
+        // Equally fertile tiles: one near the city on a choke, one near the city,
+        // one far away; plus a plain choke far away.
+        for i in [2 * 8 + 3, 2 * 8 + 4, 7 * 8 + 7] {
+            tiles[i] = 5;
+        }
+        let chokes = HashSet::from([(3, 2), (0, 7)]);
+        let values = strategic_values(&tiles, &[(2, 2)], &chokes, w, h);
+        let best = (0..64)
+            .max_by(|&a, &b| values[a].total_cmp(&values[b]))
+            .unwrap();
+        assert_eq!(best, 2 * 8 + 3);
+        assert_eq!(values[best], 1.0);
+        assert!(values[2 * 8 + 4] > values[7 * 8 + 7]);
+        assert!(values[7 * 8] > values[7 * 8 + 1]);
+        assert!(values.iter().all(|v| (0.0..=1.0).contains(v)));
+    }
 
-#[derive(GodotClass)]
-#[class(base=RefCounted, init)]
-pub struct CombatQuery;
+    #[test]
+    fn test_minimax_danger_detours_around_worst_tile() {
+        let (w, h) = (7, 7);
+        // A wall down column 3 with two gaps: a very dangerous one on the direct
+        // route and a mildly dangerous one at the top.
+        let mut blocked = vec![false; 49];
+        for y in 0..h {
+            blocked[(y * w + 3) as usize] = true;
+        }
+        blocked[(4 * w + 3) as usize] = false;
+        blocked[3] = false;
+        let mut danger = vec![0.0f32; 49];
+        danger[(4 * w + 3) as usize] = 9.0;
+        danger[3] = 1.0;
+        let path = minimax_danger_path((0, 4), (6, 4), &danger, &blocked, w, h).unwrap();
+        assert!(path.contains(&(3, 0)));
+        assert!(!path.contains(&(3, 4)));
+        assert_eq!(path.first(), Some(&(0, 4)));
+        assert_eq!(path.last(), Some(&(6, 4)));
+        // The shortest route goes through the dangerous gap
+        let shortest = astar((0, 4), (6, 4), SearchLimits::default(), |_, next| {
+            grid_index(next, w, h).filter(|&i| !blocked[i]).map(|_| 1.0)
+        })
+        .unwrap();
+        assert!(shortest.contains(&(3, 4)));
+        assert!(shortest.len() < path.len());
+    }
 
-#[godot_api]
-impl CombatQuery {
-    /// Find all pairs (attacker_idx, target_idx) where units of different owners are within radius.
-    #[func]
-    fn find_targets_in_range(
-        &self,
-        positions: PackedVector2Array,
-        owner_ids: PackedInt32Array,
-        radius: f64,
-    ) -> PackedInt32Array {
-        let r2 = (radius * radius) as f32;
-        let n = positions.len().min(owner_ids.len());
-        let pos = positions.as_slice();
-        let owners = owner_ids.as_slice();
-        let mut result = PackedInt32Array::new();
+    #[test]
+    fn test_reflect_into_bounds() {
+        let reflect = |x, y| HexMath::reflect_into_bounds(Vector2i::new(x, y), 8, 6);
+        assert_eq!(reflect(-1, 3), Vector2i::new(0, 3));
+        assert_eq!(reflect(8, 3), Vector2i::new(7, 3));
+        assert_eq!(reflect(10, -2), Vector2i::new(5, 1));
+        assert_eq!(reflect(4, 6), Vector2i::new(4, 5));
+        assert_eq!(reflect(4, 2), Vector2i::new(4, 2));
+        assert_eq!(reflect(-50, 50), Vector2i::new(7, 0));
+    }
 
-        // Simple O(n^2) — fine for <200 units on 50x50 map
-        for i in 0..n {
-            for j in 0..n {
-                if i == j {
-                    continue;
-                }
-                if owners[i] == owners[j] {
-                    continue;
-                }
-                let dx = pos[i].x - pos[j].x;
-                let dy = pos[i].y - pos[j].y;
-                if dx * dx + dy * dy <= r2 {
-                    result.push(i as i32);
-                    result.push(j as i32);
-                }
+    #[test]
+    fn test_find_paths_batch_matches_find_path() {
+        let grid = PathGrid {
+            blocked: (0..5).map(|y| (3, y)).collect(),
+            costs: HashMap::from([((1, 6), 4.0)]),
+            max_distance: 10,
+        };
+        let requests = [
+            Some(((0, 0), (6, 2))),
+            Some(((5, 5), (0, 6))),
+            Some(((2, 2), (3, 1))),
+            Some(((1, 1), (1, 1))),
+            None,
+        ];
+        let paths = grid.find_paths(&requests);
+        assert_eq!(paths.len(), requests.len());
+        for (request, path) in requests.iter().zip(&paths) {
+            let single = request.and_then(|(from, to)| grid.find_path(from, to));
+            assert_eq!(*path, single);
+        }
+        assert!(paths[0].is_some());
+        assert!(paths[2].is_none());
+        assert!(paths[4].is_none());
+    }
+
+    #[test]
+    fn test_supply_grid_respects_walls() {
+        let (w, h) = (9, 9);
+        let depot = (4, 4);
+        let mut blocked = vec![false; 81];
+        // Wall along column 5 between rows 2 and 6
+        for y in 2..=6 {
+            blocked[(y * w + 5) as usize] = true;
+        }
+        let mask = supply_grid(&[depot], 2, &blocked, w, h);
+        let dist = bfs_distance_grid(&[depot], w, h, |i| !blocked[i]);
+        for i in 0..81 {
+            assert_eq!(mask[i] == 1, (0..=2).contains(&dist[i]));
+        }
+        // Just behind the wall is inside the geometric disk but out of supply
+        assert_eq!(hex_distance_xy(depot, (6, 4)), 2);
+        assert_eq!(mask[(4 * w + 6) as usize], 0);
+        assert!(mask.iter().filter(|&&m| m == 1).count() < hex_disk(depot, 2).len());
+    }
+
+    #[test]
+    fn test_falloff_blocked_by_impassable_wall() {
+        let (w, h) = (16, 9);
+        let center = (3, 4);
+        let mut open = vec![0.0f32; w * h];
+        add_falloff(&mut open, w, h, center, 2.0, 4.0, true, &[]);
+        // Same result with an all-passable mask
+        let mut all_passable = vec![0.0f32; w * h];
+        add_falloff(
+            &mut all_passable,
+            w,
+            h,
+            center,
+            2.0,
+            4.0,
+            true,
+            &vec![1; w * h],
+        );
+        for i in 0..w * h {
+            assert!((open[i] - all_passable[i]).abs() < 1e-6);
+        }
+
+        // Wall down column 6 with a gap in the bottom row
+        let mut passability = vec![1u8; w * h];
+        for y in 0..h - 1 {
+            passability[y * w + 6] = 0;
+        }
+        let mut walled = vec![0.0f32; w * h];
+        add_falloff(&mut walled, w, h, center, 2.0, 4.0, true, &passability);
+        let at = |grid: &[f32], (x, y): (usize, usize)| grid[y * w + x];
+        assert_eq!(at(&walled, (6, 4)), 0.0);
+        assert!(at(&walled, (7, 4)) < at(&open, (7, 4)));
+        assert!(at(&walled, (9, 4)) < at(&open, (9, 4)));
+        assert_eq!(at(&walled, (2, 4)), at(&open, (2, 4)));
+    }
+
+    #[test]
+    fn test_safe_destinations_exclude_zone_of_control() {
+        let mut grid = PathGrid {
+            blocked: HashSet::new(),
+            costs: HashMap::new(),
+            max_distance: i32::MAX,
+        };
+        grid.blocked.extend(impassable_tiles(&[], &[], 10, 10));
+        let from = (4, 4);
+        let enemy = (6, 3);
+        let reachable = grid.reachable(from, 2.0, false);
+        let safe = safe_destinations(&grid, from, &[enemy], 2.0);
+        for pos in &reachable {
+            assert_eq!(safe.contains(pos), hex_distance_xy(*pos, enemy) > 1);
+        }
+        assert!(reachable.iter().any(|&p| hex_distance_xy(p, enemy) <= 1));
+        assert!(safe.contains(&from));
+
+        // The map edge limits movement
+        let corner = safe_destinations(&grid, (0, 0), &[], 1.0);
+        assert!(corner.iter().all(|&p| grid_index(p, 10, 10).is_some()));
+        assert_eq!(corner.len(), 3);
+    }
+
+    #[test]
+    fn test_grid_fingerprint_stable_and_sensitive() {
+        let tiles: Vec<i32> = (0..48).map(|i| i % 6).collect();
+        let mut owners = vec![-1; 48];
+        owners[10] = 0;
+        let a = grid_fingerprint(&[&tiles, &owners]);
+        assert_eq!(a, grid_fingerprint(&[&tiles.clone(), &owners.clone()]));
+        // Reference FNV-1a 64 values pin the byte layout across platforms
+        assert_eq!(grid_fingerprint(&[]), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(grid_fingerprint(&[&[]]), 0x4d25_767f_9dce_13f5);
+
+        owners[11] = 0;
+        assert_ne!(grid_fingerprint(&[&tiles, &owners]), a);
+        owners[11] = -1;
+        let mut changed = tiles.clone();
+        changed[47] = 0;
+        assert_ne!(grid_fingerprint(&[&changed, &owners]), a);
+        // Moving data between the grids is a different state
+        assert_ne!(
+            grid_fingerprint(&[&tiles[..47], &[tiles[47]]]),
+            grid_fingerprint(&[&tiles[..], &[]])
+        );
+    }
+
+    #[test]
+    fn test_dual_falloff_peak_and_tail() {
+        let (w, h) = (40, 9);
+        let mut map = InfluenceMap {
+            influence: Vec::new(),
+            width: w,
+            height: h,
+            num_players: 0,
+            history: VecDeque::new(),
+            history_length: 8,
+            use_hex_distance: true,
+            passability: Vec::new(),
+            falloff: Vec::new(),
+            raw: Vec::new(),
+        };
+        let center = (4, 4);
+        let mut single = vec![0.0f32; w * h];
+        map.stamp_source(&mut single, center, 1.0);
+        map.configure_dual(1.5, 1.0, 8.0, 0.2);
+        let mut dual = vec![0.0f32; w * h];
+        map.stamp_source(&mut dual, center, 1.0);
+
+        let at = |grid: &[f32], x: usize| grid[4 * w + x];
+        // Taller peak, faster drop nearby, and a long low tail past the single cutoff
+        assert!(at(&dual, 4) > at(&single, 4));
+        assert!(at(&dual, 6) < at(&single, 6));
+        assert_eq!(at(&single, 18), 0.0);
+        assert!(at(&dual, 18) > 0.0 && at(&dual, 18) < 0.1);
+
+        // Bad parameters, or no far component, fall back to the single Gaussian
+        for (sn, wn, sf, wf) in [
+            (0.0, 1.0, 8.0, 0.2),
+            (1.5, 1.0, -2.0, 0.2),
+            (f32::NAN, 1.0, 8.0, 0.2),
+            (1.5, f32::INFINITY, 8.0, 0.2),
+            (1.5, 1.0, 8.0, 0.0),
+        ] {
+            map.configure_dual(1.5, 1.0, 8.0, 0.2);
+            map.configure_dual(sn, wn, sf, wf);
+            assert!(map.falloff.is_empty());
+            let mut reset = vec![0.0f32; w * h];
+            map.stamp_source(&mut reset, center, 1.0);
+            assert_eq!(reset, single);
+        }
+    }
+
+    #[test]
+    fn test_owned_tiles_and_count() {
+        let grid = [0, 1, -1, 1, 1, 0, -1, -1, 1, 0, 2, 1];
+        let tiles = owned_positions(&grid, 1, 4);
+        assert_eq!(tiles, vec![(1, 0), (3, 0), (0, 1), (0, 2), (3, 2)]);
+        assert_eq!(tiles.len(), grid.iter().filter(|&&o| o == 1).count());
+        assert_eq!(owned_positions(&grid, 2, 4), vec![(2, 2)]);
+        assert!(owned_positions(&grid, 7, 4).is_empty());
+    }
+
+    #[test]
+    fn test_obstacle_distance_field_rings() {
+        // The off-map obstacle is ignored
+        let field = obstacle_distances(&[(4, 4), (-3, 2)], 9, 9);
+        assert_eq!(field.len(), 81);
+        for y in 0..9 {
+            for x in 0..9 {
+                assert_eq!(field[(y * 9 + x) as usize], hex_distance_xy((4, 4), (x, y)));
             }
         }
-        result
+        let two = obstacle_distances(&[(0, 0), (8, 8)], 9, 9);
+        for (i, &d) in two.iter().enumerate() {
+            let pos = (i as i32 % 9, i as i32 / 9);
+            let nearest = hex_distance_xy((0, 0), pos).min(hex_distance_xy((8, 8), pos));
+            assert_eq!(d, nearest);
+        }
+        assert!(obstacle_distances(&[], 3, 3).iter().all(|&d| d == -1));
     }
-}
 
-// ============================================================
-// 4. ResourceCounter
-// ============================================================
+    #[test]
+    fn test_visible_tiles_open_field_and_shadow() {
+        let (w, h) = (11, 11);
+        let from = (5, 5);
+        let mut tiles = vec![0; 121];
+        let open = visible_tiles(from, 3, &tiles, &[2], w, h);
+        assert_eq!(
+            open.iter().collect::<HashSet<_>>(),
+            hex_disk(from, 3).iter().collect::<HashSet<_>>()
+        );
+        assert_eq!(open[0], from);
+        assert!(open
+            .windows(2)
+            .all(|p| hex_distance_xy(from, p[0]) <= hex_distance_xy(from, p[1])));
 
-#[derive(GodotClass)]
-#[class(base=RefCounted, init)]
-pub struct ResourceCounter;
+        let mountain = (5, 3);
+        tiles[(mountain.1 * w + mountain.0) as usize] = 2;
+        let shadowed = visible_tiles(from, 3, &tiles, &[2], w, h);
+        // The mountain is seen, the tile straight behind it is not
+        assert!(shadowed.contains(&mountain));
+        assert!(!shadowed.contains(&(5, 2)));
+        for pos in hex_disk(from, 3) {
+            let to = Vector2i::new(pos.0, pos.1);
+            let clear = line_cover(Vector2i::new(5, 5), to, &tiles, &[2], w, h) == 0.0;
+            assert_eq!(shadowed.contains(&pos), clear);
+        }
+    }
 
-#[godot_api]
-impl ResourceCounter {
-    /// Returns Dictionary { player_id -> PackedInt32Array [food, production, gold] }
-    #[func]
-    fn compute_resources(
-        &self,
-        tile_types: PackedInt32Array,
-        owner_grid: PackedInt32Array,
-        num_players: i32,
-    ) -> Dictionary<Variant, Variant> {
-        let np = num_players as usize;
-        let mut totals = vec![[0i32; 3]; np];
+    #[test]
+    fn test_overlay_grids_replaces_only_non_sentinel() {
+        let base = [0, 0, 1, 1, -1, 2];
+        let overlay = [-9, 3, -9, -9, -1, -9];
+        assert_eq!(
+            overlay_values(&base, &overlay, -9),
+            Some(vec![0, 3, 1, 1, -1, 2])
+        );
+        // Mismatched lengths are rejected
+        assert_eq!(overlay_values(&base, &[5, 5], -9), None);
+    }
 
-        let n = tile_types.len().min(owner_grid.len());
-        for i in 0..n {
-            let owner = owner_grid[i];
-            if owner < 0 || owner as usize >= np {
-                continue;
+    #[test]
+    fn test_amphibious_reach_through_adjacent_transport() {
+        // 8x6 map: columns 0-2 land, 3-7 water
+        let (w, h) = (8, 6);
+        let tiles: Vec<i32> = (0..w * h).map(|i| if i % w < 3 { 0 } else { 3 }).collect();
+        let from = (2, 2);
+        let transport = (3, 2);
+        let far_transport = (7, 5);
+        let (land, sea, boardable) =
+            amphibious_tiles(from, &[transport, far_transport], 1.0, 2.0, &tiles, w, h);
+        assert_eq!(boardable, vec![0]);
+        assert!(land.contains(&from));
+        assert!(land.iter().all(|&(x, _)| x < 3));
+        // Exactly the water tiles within two moves of the transport
+        for y in 0..h {
+            for x in 3..w {
+                assert_eq!(
+                    sea.contains(&(x, y)),
+                    hex_distance_xy(transport, (x, y)) <= 2,
+                    "{:?}",
+                    (x, y)
+                );
             }
-            let (f, p, g) = match tile_types[i] {
-                0 => (1, 1, 0), // plains
-                1 => (0, 2, 0), // forest
-                2 => (0, 3, 1), // mountain
-                3 => (0, 0, 2), // water
-                4 => (1, 0, 1), // desert
-                5 => (3, 1, 0), // plains_fertile
-                _ => (0, 0, 0),
-            };
-            let pid = owner as usize;
-            totals[pid][0] += f;
-            totals[pid][1] += p;
-            totals[pid][2] += g;
         }
+        assert!(sea.iter().all(|&(x, _)| x >= 3));
 
-        let mut dict = Dictionary::new();
-        for (pid, totals_pid) in totals.iter().enumerate().take(np) {
-            let mut arr = PackedInt32Array::new();
-            arr.push(totals_pid[0]);
-            arr.push(totals_pid[1]);
-            arr.push(totals_pid[2]);
-            let k = Variant::from(pid as i32);
-            let v = Variant::from(arr);
-            dict.set(&k, &v);
+        // No transport in reach: land only
+        let (_, sea, boardable) = amphibious_tiles(from, &[far_transport], 1.0, 2.0, &tiles, w, h);
+        assert!(sea.is_empty() && boardable.is_empty());
+    }
+
+    #[test]
+    fn test_find_path_directional_follows_current() {
+        let grid = PathGrid {
+            blocked: HashSet::new(),
+            costs: HashMap::new(),
+            max_distance: 20,
+        };
+        let (from, to) = ((4, 1), (4, 7));
+        assert_eq!(
+            grid.find_path_directional(from, to, &[1.0; 6]),
+            grid.find_path(from, to)
+        );
+        // Sailing straight south (direction 5) is against the wind, while the
+        // south-east and south-west legs (0 and 4) are cheap: tacking takes twice the
+        // moves but costs far less.
+        let wind = [0.2, 3.0, 3.0, 3.0, 0.2, 3.0];
+        let path = grid.find_path_directional(from, to, &wind).unwrap();
+        assert_eq!(path.len(), 13);
+        for step in path.windows(2) {
+            let d = direction_between_xy(step[0], step[1]).unwrap();
+            assert!(d == 0 || d == 4, "{:?}", step);
         }
-        dict
+        assert_eq!(grid.find_path(from, to).unwrap().len(), 7);
     }
-}
 
-// ============================================================
-// 5. HexLOS
-// ============================================================
+    #[test]
+    fn test_upscale_then_downscale_round_trips() {
+        let coarse: Vec<i32> = vec![0, 1, 2, -1, 3, 3];
+        let fine = upscale_values(&coarse, 3, 2, 3);
+        assert_eq!(fine.len(), 9 * 6);
+        assert_eq!(fine[0], 0);
+        assert_eq!(fine[8], 2);
+        assert_eq!(fine[5 * 9 + 3], 3);
+        assert_eq!(downscale_values(&fine, 9, 6, 3), coarse);
 
-#[derive(GodotClass)]
-#[class(base=RefCounted, init)]
-pub struct HexLOS;
+        // Majority vote, ties to the smaller value, partial edge blocks
+        let noisy = [1, 1, 2, 7, 1, 2, 2, 7];
+        assert_eq!(downscale_values(&noisy, 4, 2, 2), vec![1, 2]);
+        assert_eq!(downscale_values(&noisy, 4, 2, 3), vec![1, 7]);
+    }
 
-#[godot_api]
-impl HexLOS {
-    /// Line-of-sight check: returns true if no mountain (type=2) blocks the line from→to.
-    /// Uses cube-coordinate lerp to walk hex tiles along the line.
-    #[func]
-    fn has_line_of_sight(
-        &self,
-        from: Vector2i,
-        to: Vector2i,
-        tile_types: PackedInt32Array,
-        map_width: i32,
-        map_height: i32,
-    ) -> bool {
-        let w = map_width as usize;
-        let dist = HexMath::hex_distance(from, to);
-        if dist <= 1 {
-            return true;
+    #[test]
+    fn test_influence_gradient_points_downhill() {
+        // Net influence falls off to the east (higher x)
+        let (w, h) = (8, 6);
+        let slope: Vec<f32> = (0..w * h).map(|i| 10.0 - (i % w) as f32).collect();
+        let map = InfluenceMap {
+            influence: vec![slope],
+            width: w,
+            height: h,
+            num_players: 1,
+            history: VecDeque::new(),
+            history_length: 8,
+            use_hex_distance: false,
+            passability: Vec::new(),
+            falloff: Vec::new(),
+            raw: Vec::new(),
+        };
+        for pos in [(2, 2), (3, 2), (5, 4), (0, 0)] {
+            let d = map.influence_gradient_direction(0, Vector2i::new(pos.0, pos.1));
+            // Both eastward neighbors are equally low; the lower index (0) wins
+            assert_eq!(d, 0, "{:?}", pos);
         }
+        assert_eq!(
+            map.influence_gradient_direction(0, Vector2i::new(20, 20)),
+            -1
+        );
+        assert_eq!(map.influence_gradient_direction(3, Vector2i::new(2, 2)), -1);
+    }
 
-        // Convert to cube coords
-        let (ax, ay) = to_axial(from);
-        let az = -ax - ay;
-        let (bx, by) = to_axial(to);
-        let bz = -bx - by;
+    #[test]
+    fn test_unit_index_stack_limit() {
+        let mut index = UnitIndex {
+            units_by_tile: HashMap::new(),
+        };
+        let tile = Vector2i::new(2, 3);
+        let mut tiles = Vec::new();
+        for count in 0..3 {
+            index.index_tiles(&tiles);
+            assert_eq!(index.stack_count(tile), count);
+            assert_eq!(index.can_enter(tile, 2), count < 2);
+            tiles.push((2, 3));
+        }
+        assert!(!index.can_enter(tile, 0));
+        assert!(index.can_enter(Vector2i::new(0, 0), 1));
+    }
 
-        // Walk intermediate tiles (skip endpoints)
-        for step in 1..dist {
-            let t = step as f64 / dist as f64;
-            // Lerp in cube space
-            let fx = ax as f64 + (bx - ax) as f64 * t;
-            let fy = ay as f64 + (by - ay) as f64 * t;
-            let fz = az as f64 + (bz - az) as f64 * t;
+    #[test]
+    fn test_find_k_shortest_paths_around_ring() {
+        // Only a radius-2 ring around (4, 4) is open, plus a one-tile stem off its
+        // east corner: every route from the stem shares the corner tile.
+        let (cq, cr) = to_axial(Vector2i::new(4, 4));
+        let corner = |dir: usize, radius: i32| {
+            let (dq, dr) = AXIAL_DIRS[dir];
+            from_axial(cq + radius * dq, cr + radius * dr)
+        };
+        let ring: HashSet<(i32, i32)> = hex_disk((4, 4), 2)
+            .into_iter()
+            .filter(|&p| hex_distance_xy(p, (4, 4)) == 2)
+            .collect();
+        let (from, to) = (corner(0, 3), corner(2, 2));
+        let mut grid = PathGrid {
+            blocked: (-1..=9)
+                .flat_map(|y| (-1..=9).map(move |x| (x, y)))
+                .filter(|p| !ring.contains(p) && *p != from)
+                .collect(),
+            costs: HashMap::new(),
+            max_distance: i32::MAX,
+        };
+        let paths = grid.find_k_shortest_paths(from, to, 3);
+        // The short way round (4 ring steps) first, then the long way (8)
+        assert_eq!(paths.len(), 2);
+        assert_eq!((paths[0].len(), paths[1].len()), (6, 10));
+        for p in &paths {
+            assert_eq!((p[0], p[1], *p.last().unwrap()), (from, corner(0, 2), to));
+            assert_eq!(p.iter().collect::<HashSet<_>>().len(), p.len());
+            assert!(p.windows(2).all(|s| hex_distance_xy(s[0], s[1]) == 1));
+            assert!(p.iter().all(|t| !grid.blocked.contains(t)));
+        }
+        assert_ne!(paths[0], paths[1]);
 
-            // Round to nearest cube hex
-            let (rx, ry, _rz) = cube_round(fx, fy, fz);
+        // Cut the long side: only one route left
+        grid.blocked.insert(corner(4, 2));
+        assert_eq!(
+            grid.find_k_shortest_paths(from, to, 3),
+            vec![paths[0].clone()]
+        );
+        assert!(grid.find_k_shortest_paths(from, to, 0).is_empty());
 
-            // Convert axial back to odd-q offset
-            let col = rx;
-            let row = ry + (rx - (rx & 1)) / 2;
+        // Open ground with costly tiles: distinct routes in non-decreasing cost order
+        let open = PathGrid {
+            blocked: impassable_tiles(&[], &[], 7, 7),
+            costs: HashMap::from([((2, 2), 3.0), ((3, 3), 2.0), ((4, 2), 5.0)]),
+            max_distance: i32::MAX,
+        };
+        let routes = open.find_k_shortest_paths((0, 2), (6, 2), 6);
+        assert_eq!(routes.len(), 6);
+        assert_eq!(routes[0], open.find_path((0, 2), (6, 2)).unwrap());
+        let cost = |p: &Vec<(i32, i32)>| p[1..].iter().map(|&t| open.cost(t)).sum::<f64>();
+        assert!(routes.windows(2).all(|r| cost(&r[0]) <= cost(&r[1])));
+        assert_eq!(routes.iter().collect::<HashSet<_>>().len(), 6);
+    }
 
-            if col < 0 || row < 0 || col >= map_width || row >= map_height {
-                return false; // out of bounds blocks LOS
-            }
-            let idx = row as usize * w + col as usize;
-            if idx < tile_types.len() && tile_types[idx] == 2 {
-                return false; // mountain blocks
-            }
+    #[test]
+    fn test_remap_owners_neutralizes_one_player() {
+        let grid = [3, 0, 3, -1, 1, 3, 2];
+        let mut mapping = HashMap::from([(3, -1)]);
+        assert_eq!(
+            remapped_owners(&grid, &mapping),
+            vec![-1, 0, -1, -1, 1, -1, 2]
+        );
+
+        // Swaps apply once rather than chaining
+        mapping.extend([(0, 1), (1, 0)]);
+        assert_eq!(
+            remapped_owners(&grid, &mapping),
+            vec![-1, 1, -1, -1, 0, -1, 2]
+        );
+    }
+
+    #[test]
+    fn test_impact_tile_stops_before_blocker() {
+        let (w, h) = (10, 10);
+        let mut tiles = vec![0; 100];
+        let (from, to) = (Vector2i::new(2, 5), Vector2i::new(2, 1));
+        assert_eq!(line_impact(from, to, &tiles, &[2], w, h), (2, 1));
+        // Mountain two tiles along the line
+        tiles[3 * 10 + 2] = 2;
+        assert_eq!(line_impact(from, to, &tiles, &[2], w, h), (2, 4));
+        // Blocked right next to the shooter
+        tiles[4 * 10 + 2] = 2;
+        assert_eq!(line_impact(from, to, &tiles, &[2], w, h), (2, 5));
+        // The map edge stops a shot aimed off the map
+        let edge = line_impact(
+            Vector2i::new(2, 2),
+            Vector2i::new(2, -3),
+            &tiles,
+            &[2],
+            w,
+            h,
+        );
+        assert_eq!(edge, (2, 0));
+    }
+
+    #[test]
+    fn test_blast_damage_falls_off_per_ring() {
+        let damage: HashMap<(i32, i32), i32> = blast_damage_tiles((5, 5), 50, 2, 20, 10, 10)
+            .into_iter()
+            .collect();
+        assert_eq!(damage.len(), 19);
+        assert_eq!(damage.get(&(5, 5)), Some(&50));
+        assert_eq!(damage.get(&(5, 4)), Some(&30));
+        assert_eq!(damage.get(&(5, 3)), Some(&10));
+        assert_eq!(damage.get(&(5, 2)), None);
+
+        // Clamped at zero and clipped to the map
+        let corner: HashMap<(i32, i32), i32> = blast_damage_tiles((0, 0), 10, 2, 20, 10, 10)
+            .into_iter()
+            .collect();
+        assert!(corner.len() < 19);
+        assert_eq!(corner.get(&(0, 2)), Some(&0));
+        assert!(corner.keys().all(|&p| grid_index(p, 10, 10).is_some()));
+    }
+
+    #[test]
+    fn test_next_step_revalidates() {
+        let path: Vec<(i32, i32)> = (0..4).map(|y| (2, y)).collect();
+        let mut blocked = HashSet::from([(5, 5)]);
+        assert_eq!(path_next_step(&path, (2, 1), &blocked), Some((2, 2)));
+        assert_eq!(path_next_step(&path, (2, 3), &blocked), Some((2, 3)));
+        assert_eq!(path_next_step(&path, (7, 7), &blocked), None);
+        // A unit moved onto the next tile since the path was planned
+        blocked.insert((2, 2));
+        assert_eq!(path_next_step(&path, (2, 1), &blocked), None);
+        assert_eq!(path_next_step(&path, (2, 0), &blocked), Some((2, 1)));
+    }
+
+    #[test]
+    fn test_owner_histogram_single_pass() {
+        let grid = [0, 1, -1, 2, 1, 1, 7, 0, -1, 2];
+        let counts = owner_counts(&grid, 3);
+        assert_eq!(counts, vec![2, 3, 2]);
+        let owned = grid.iter().filter(|&&o| (0..3).contains(&o)).count();
+        assert_eq!(counts.iter().sum::<i32>() as usize, owned);
+        assert_eq!(owner_counts(&grid, 0), Vec::<i32>::new());
+    }
+
+    #[test]
+    fn test_closest_in_set() {
+        let target = (2, 3);
+        let candidates = [(9, 9), (4, 1), (1, 4), (2, 6)];
+        assert_eq!(closest_tile(target, &candidates), Some((1, 4)));
+        // (3, 3) and (1, 4) are both adjacent to (2, 3): the earlier one wins
+        assert_eq!(closest_tile(target, &[(3, 3), (1, 4)]), Some((3, 3)));
+        assert_eq!(closest_tile(target, &[]), None);
+    }
+
+    #[test]
+    fn test_border_pressure_counts_enemy_neighbors() {
+        let (w, h) = (5, 5);
+        let mut grid = vec![0; 25];
+        // Player 1 holds the east column and pokes in at (3, 1), so (3, 2) touches
+        // three of its tiles: (3, 1), (4, 2) and (4, 3).
+        for y in 0..5 {
+            grid[y * 5 + 4] = 1;
         }
-        true
+        grid[5 + 3] = 1;
+        let pressure: HashMap<(i32, i32), i32> = border_pressure_counts(&grid, 0, 1, w, h)
+            .into_iter()
+            .collect();
+        assert_eq!(pressure[&(3, 2)], 3);
+        assert_eq!(pressure[&(3, 4)], 1);
+        // Interior tiles are not border tiles
+        assert!(!pressure.contains_key(&(1, 2)));
+        assert!(!pressure.contains_key(&(3, 1)));
+        assert!(border_pressure_counts(&grid, 0, 2, w, h)
+            .iter()
+            .all(|&(_, c)| c == 0));
     }
-}
 
-fn cube_round(x: f64, y: f64, z: f64) -> (i32, i32, i32) {
-    let mut rx = x.round();
-    let mut ry = y.round();
-    let mut rz = z.round();
+    #[test]
+    fn test_road_network_connects_cities_in_line() {
+        let mut grid = PathGrid {
+            blocked: impassable_tiles(&[], &[], 12, 5),
+            costs: HashMap::new(),
+            max_distance: 12 + 5,
+        };
+        // Cities on the blocked ring are skipped; ones beyond it are unreachable
+        let cities = [(1, 2), (-3, 2), (12, 2), (10, 2), (5, 2), (20, -4)];
+        let roads = grid.road_network(&cities);
+        let set: HashSet<(i32, i32)> = roads.iter().copied().collect();
+        assert_eq!(set.len(), roads.len());
+        assert!([(1, 2), (10, 2), (5, 2)].iter().all(|c| set.contains(c)));
+        assert!(!set.contains(&(-3, 2)) && !set.contains(&(12, 2)) && !set.contains(&(20, -4)));
+        // The middle city links to both ends, so the road is one shortest line
+        assert_eq!(roads.len(), hex_distance_xy((1, 2), (10, 2)) as usize + 1);
+        let component = connected_region((1, 2), 12, 5, |i| {
+            set.contains(&((i % 12) as i32, (i / 12) as i32))
+        });
+        assert_eq!(component.len(), set.len());
 
-    let dx = (rx - x).abs();
-    let dy = (ry - y).abs();
-    let dz = (rz - z).abs();
+        // An unreachable city is left out
+        grid.blocked.extend((0..5).map(|y| (8, y)));
+        let roads = grid.road_network(&cities);
+        assert!(!roads.contains(&(10, 2)));
+        assert!(roads.contains(&(5, 2)));
+    }
 
-    if dx > dy && dx > dz {
-        rx = -ry - rz;
-    } else if dy > dz {
-        ry = -rx - rz;
-    } else {
-        rz = -rx - ry;
+    #[test]
+    fn test_find_path_owned_detours_around_foreign_territory() {
+        let grid = PathGrid {
+            blocked: HashSet::new(),
+            costs: HashMap::new(),
+            max_distance: 10,
+        };
+        let (w, h) = (9, 7);
+        // Player 2 owns a block in the middle; the mover is player 0
+        let mut owners = vec![-1; (w * h) as usize];
+        for y in 1..6 {
+            for x in 3..6 {
+                owners[(y * w + x) as usize] = 2;
+            }
+        }
+        owners[(3 * w + 1) as usize] = 0;
+        let (from, to) = ((1, 3), (7, 3));
+        let direct = grid.find_path(from, to).unwrap();
+        assert!(direct
+            .iter()
+            .any(|&(x, y)| owners[(y * w + x) as usize] == 2));
+        let path = grid
+            .find_path_owned(from, to, &owners, &[0, -1], w)
+            .unwrap();
+        assert!(path.iter().all(|&(x, y)| owners[(y * w + x) as usize] != 2));
+        assert!(path.len() > direct.len());
+        // No restriction
+        assert_eq!(
+            grid.find_path_owned(from, to, &owners, &[], w),
+            Some(direct)
+        );
+        // The goal itself is foreign
+        assert_eq!(
+            grid.find_path_owned(from, (4, 3), &owners, &[0, -1], w),
+            None
+        );
     }
-    let _ = rz;
-    (rx as i32, ry as i32, rz as i32)
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    #[test]
+    fn test_recompute_region_matches_full_compute() {
+        let (w, h) = (40, 30);
+        // Raw grids stamped the way `compute` stamps units, then a full net refresh
+        let seeded = |units: [&[(i32, i32)]; 2]| {
+            let mut map = InfluenceMap {
+                influence: vec![vec![0.0; w * h]; 2],
+                width: w,
+                height: h,
+                num_players: 2,
+                history: VecDeque::new(),
+                history_length: 8,
+                use_hex_distance: false,
+                passability: Vec::new(),
+                falloff: Vec::new(),
+                raw: Vec::new(),
+            };
+            map.raw = units
+                .iter()
+                .map(|tiles| {
+                    let mut grid = vec![0.0; w * h];
+                    for &tile in *tiles {
+                        map.stamp_source(&mut grid, tile, UNIT_INFLUENCE);
+                    }
+                    grid
+                })
+                .collect();
+            map.refresh_net(0, 0, w as i32 - 1, h as i32 - 1);
+            map
+        };
+        let mut incremental = seeded([&[(3, 3), (5, 4)], &[(30, 20), (34, 25)]]);
+        // A skirmish in one corner
+        incremental.move_unit(0, Vector2i::new(5, 4), Vector2i::new(6, 5));
+        incremental.move_unit(1, Vector2i::new(34, 25), Vector2i::new(33, 24));
+        incremental.recompute_region(Vector2i::new(5, 4), Vector2i::new(6, 5));
+        incremental.recompute_region(Vector2i::new(33, 24), Vector2i::new(34, 25));
+
+        let full = seeded([&[(3, 3), (6, 5)], &[(30, 20), (33, 24)]]);
+        for pid in 0..2 {
+            for i in 0..w * h {
+                let (a, b) = (incremental.influence[pid][i], full.influence[pid][i]);
+                assert!(
+                    (a - b).abs() < 1e-4,
+                    "player {} cell {}: {} vs {}",
+                    pid,
+                    i,
+                    a,
+                    b
+                );
+            }
+        }
+    }
 
     #[test]
-    fn test_hex_distance_same() {
+    fn test_pixel_lerp_endpoints_and_midpoint() {
+        let size = Vector2::new(20.0, 16.0);
+        let (from, to) = (Vector2i::new(1, 2), Vector2i::new(4, 0));
+        let a = hex_center(from, size, Vector2::ZERO);
+        let b = hex_center(to, size, Vector2::ZERO);
+        assert_eq!(HexMath::pixel_lerp(from, to, 0.0, size), a);
+        assert_eq!(HexMath::pixel_lerp(from, to, 1.0, size), b);
+        let mid = HexMath::pixel_lerp(from, to, 0.5, size);
+        assert!((mid.x - (a.x + b.x) / 2.0).abs() < 1e-4);
+        assert!((mid.y - (a.y + b.y) / 2.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_valid_spawn_tiles_excludes_blocked_and_full() {
+        let mut units = UnitIndex {
+            units_by_tile: HashMap::new(),
+        };
+        units.index_tiles(&[(1, 0), (1, 0), (2, 0)]);
+        let mut tiles = vec![0; 8];
+        tiles[3] = 3; // water at (3, 0)
+        let region: Vec<(i32, i32)> = (0..5).map(|x| (x, 0)).collect();
+        // (1, 0) is full, (3, 0) is water, (4, 0) is off the map
+        let spawn = spawn_tiles(&region, &tiles, &[3], &units, 2, 4);
+        assert_eq!(spawn, vec![(0, 0), (2, 0)]);
+        // With room for three per tile the stack at (1, 0) opens up
         assert_eq!(
-            HexMath::hex_distance(Vector2i::new(0, 0), Vector2i::new(0, 0)),
-            0
+            spawn_tiles(&region, &tiles, &[3], &units, 3, 4),
+            vec![(0, 0), (1, 0), (2, 0)]
         );
     }
 
     #[test]
-    fn test_hex_distance_adjacent() {
+    fn test_label_water_bodies_separates_lakes() {
+        let (w, h) = (7, 4);
+        let mut tiles = [0; 28];
+        // Two lakes split by a land column at x = 3
+        for (x, y) in [(0, 0), (1, 0), (1, 1), (5, 2), (6, 2), (5, 3)] {
+            tiles[y * 7 + x] = 3;
+        }
+        let labels = label_components(w, h, |i| tiles[i] == 3);
+        let at = |x: usize, y: usize| labels[y * 7 + x];
+        assert_eq!((at(0, 0), at(1, 0), at(1, 1)), (0, 0, 0));
+        assert_eq!((at(5, 2), at(6, 2), at(5, 3)), (1, 1, 1));
+        assert_eq!(labels.iter().filter(|&&l| l == -1).count(), 22);
+    }
+
+    #[test]
+    fn test_bearing_due_east_is_zero() {
+        let size = Vector2::new(32.0, 32.0);
+        // Two columns over in odd-q keeps the same row parity, so the same pixel y
+        assert!(HexMath::bearing(Vector2i::new(1, 3), Vector2i::new(3, 3), size).abs() < 1e-5);
+        let west = HexMath::bearing(Vector2i::new(3, 3), Vector2i::new(1, 3), size);
+        assert!((west.abs() - std::f32::consts::PI).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_region_diameter_open_rectangle() {
+        let (w, h) = (8, 5);
+        let estimate = region_diameter_estimate((3, 2), &HashSet::new(), w, h);
+        // True diameter by BFS from every tile
+        let exact = (0..w * h)
+            .map(|i| {
+                let dist = bfs_distance_grid(&[(i % w, i / w)], w, h, |_| true);
+                *dist.iter().max().unwrap()
+            })
+            .max()
+            .unwrap();
+        assert!(estimate <= exact && estimate >= exact - 1);
+        assert_eq!(estimate, 8);
+        // A wall splitting the map leaves a smaller region around the start
+        let wall: HashSet<(i32, i32)> = (0..h).map(|y| (4, y)).collect();
+        assert!(region_diameter_estimate((1, 2), &wall, w, h) < estimate);
         assert_eq!(
-            HexMath::hex_distance(Vector2i::new(0, 0), Vector2i::new(1, 0)),
-            1
+            region_diameter_estimate((3, 2), &HashSet::from([(3, 2)]), w, h),
+            -1
         );
+        assert_eq!(region_diameter_estimate((9, 2), &HashSet::new(), w, h), -1);
     }
 
     #[test]
-    fn test_hex_distance_far() {
-        let d = HexMath::hex_distance(Vector2i::new(0, 0), Vector2i::new(3, 3));
-        assert!(d > 0);
+    fn test_nav_grid_save_load_round_trip() {
+        let (w, h) = (5, 4);
+        let mut costs = vec![1.0; 20];
+        costs[7] = 4.5;
+        let mut blocked = vec![0u8; 20];
+        blocked[2] = 1;
+        blocked[12] = 1;
+        let mut baked = NavGrid {
+            width: 0,
+            height: 0,
+            costs: Vec::new(),
+            blocked: Vec::new(),
+            terrain_version: 0,
+        };
+        baked.set_terrain(w, h, &costs, &blocked);
+        let data = baked.encode();
+
+        let mut loaded = NavGrid {
+            width: 0,
+            height: 0,
+            costs: Vec::new(),
+            blocked: Vec::new(),
+            terrain_version: 0,
+        };
+        assert!(loaded.decode(&data));
+        assert_eq!((loaded.width, loaded.height), (w, h));
+        for (from, to) in [((0, 0), (4, 3)), ((1, 0), (3, 0)), ((0, 3), (4, 0))] {
+            let before = baked.path(from, to);
+            assert!(before.is_some());
+            assert_eq!(loaded.path(from, to), before);
+        }
+        // Off-map ends give no path instead of searching the open plane
+        assert_eq!(loaded.path((-2, 0), (2, 2)), None);
+        assert_eq!(loaded.path((0, 0), (5, 1)), None);
+
+        // A different version is rejected without touching the loaded terrain
+        let mut stale = data.clone();
+        stale[0] = 2;
+        assert!(!loaded.decode(&stale));
+        assert!(!loaded.decode(&data[..data.len() - 1]));
+        assert_eq!(loaded.encode(), data);
     }
 
     #[test]
-    fn test_cube_round() {
-        let (x, y, z) = cube_round(0.1, -0.2, 0.1);
-        assert_eq!(x + y + z, 0);
+    fn test_nav_grid_set_tile_cost_opens_shortcut() {
+        let mut nav = NavGrid {
+            width: 0,
+            height: 0,
+            costs: Vec::new(),
+            blocked: Vec::new(),
+            terrain_version: 0,
+        };
+        let mut costs = vec![2.0; 15];
+        costs[2] = 10.0;
+        nav.set_terrain(5, 3, &costs, &[]);
+        let version = nav.terrain_version();
+        let before = nav.path((0, 0), (4, 0)).unwrap();
+        assert!(!before.contains(&(2, 0)));
+
+        nav.set_tile_cost(Vector2i::new(2, 0), 1.0);
+        let after = nav.path((0, 0), (4, 0)).unwrap();
+        assert!(after.contains(&(2, 0)));
+        assert!(nav.terrain_version() > version);
+
+        nav.set_tile_blocked(Vector2i::new(2, 0), true);
+        let blocked = nav.path((0, 0), (4, 0)).unwrap();
+        assert!(!blocked.contains(&(2, 0)));
     }
 }